@@ -0,0 +1,360 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse::ParseStream, parse_macro_input, Data, DeriveInput, Expr, Field, Fields, GenericArgument, Ident,
+    LitStr, PathArguments, Token, Type,
+};
+
+#[proc_macro_derive(Model, attributes(model, primary_key, index, unique, belongs_to, version))]
+pub fn derive_model(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+struct ModelAttrs {
+    tree: String,
+    hooks: bool,
+    has_many: Vec<(Ident, Type)>,
+    key_strategy: Option<TokenStream2>,
+}
+
+struct BelongsTo<'a> {
+    field: &'a Field,
+    parent: Type,
+    accessor: Ident,
+    on_delete: Option<TokenStream2>,
+}
+
+struct PrimaryKey {
+    ident: Ident,
+    ty: Type,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let ModelAttrs { tree, hooks, has_many, key_strategy } = model_attrs(&input)?;
+    let fields = named_fields(&input)?;
+    let pk = primary_key(&input, &fields)?;
+    let indexes = indexes(&fields);
+    let version = version(&fields)?;
+    let relations = belongs_to(&fields)?;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let hooks = hooks.then(|| quote!(tree.use_model_hooks::<Self>();));
+    let key_strategy = key_strategy.map(|strategy| quote!(tree.set_key_strategy(::sled_orm::KeyStrategy::#strategy)?;));
+    let pk_ident = &pk.ident;
+    let pk_ty = &pk.ty;
+
+    let relation_indexes = relations.iter().map(|relation| {
+        let parent = &relation.parent;
+        quote!(tree.belongs_to::<Self, #parent>()?;)
+    });
+    let relation_impls = relations.iter().map(|relation| {
+        let parent = &relation.parent;
+        let field = relation.field.ident.as_ref().expect("named field");
+        let foreign_key = field.to_string();
+        let (parent_key, clear_parent_key) = if option_inner(&relation.field.ty).is_some() {
+            (
+                quote!(::core::clone::Clone::clone(&self.#field)),
+                Some(quote! {
+                    fn clear_parent_key(&mut self) {
+                        self.#field = ::core::option::Option::None;
+                    }
+                }),
+            )
+        } else {
+            (quote!(::core::option::Option::Some(::core::clone::Clone::clone(&self.#field))), None)
+        };
+        let on_delete = relation.on_delete.as_ref().map(|on_delete| {
+            quote!(const ON_DELETE: ::sled_orm::OnDelete = ::sled_orm::OnDelete::#on_delete;)
+        });
+        quote! {
+            impl #impl_generics ::sled_orm::BelongsTo<#parent> for #name #ty_generics #where_clause {
+                const FOREIGN_KEY: &'static str = #foreign_key;
+                #on_delete
+
+                fn parent_key(&self) -> ::core::option::Option<<#parent as ::sled_orm::Model>::PrimaryKey> {
+                    #parent_key
+                }
+
+                #clear_parent_key
+            }
+        }
+    });
+    let parent_accessors = relations.iter().map(|relation| {
+        let parent = &relation.parent;
+        let accessor = &relation.accessor;
+        quote! {
+            pub fn #accessor(&self, orm: &::sled_orm::ORM) -> ::core::result::Result<::core::option::Option<#parent>, ::sled_orm::OrmError> {
+                <Self as ::sled_orm::BelongsTo<#parent>>::parent(self, orm)
+            }
+        }
+    });
+    let children_constraints = has_many.iter().map(|(_, child)| quote!(tree.has_many::<#child, Self>()?;));
+    let children_accessors = has_many.iter().map(|(accessor, child)| {
+        quote! {
+            pub fn #accessor(&self, orm: &::sled_orm::ORM) -> ::core::result::Result<::std::vec::Vec<#child>, ::sled_orm::OrmError> {
+                <#child as ::sled_orm::BelongsTo<Self>>::children_of(self, orm)
+            }
+        }
+    });
+
+    Ok(quote! {
+        #(#relation_impls)*
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#parent_accessors)*
+            #(#children_accessors)*
+        }
+
+        impl #impl_generics ::sled_orm::Model for #name #ty_generics #where_clause {
+            type PrimaryKey = #pk_ty;
+
+            const TREE: &'static str = #tree;
+
+            fn key(&self) -> &Self::PrimaryKey {
+                &self.#pk_ident
+            }
+
+            fn set_key(&mut self, key: Self::PrimaryKey) {
+                self.#pk_ident = key;
+            }
+
+            fn configure(tree: &::sled_orm::Tree) -> ::core::result::Result<(), ::sled_orm::OrmError> {
+                #(#indexes)*
+                #(#relation_indexes)*
+                #(#children_constraints)*
+                #hooks
+                #key_strategy
+                #version
+                ::core::result::Result::Ok(())
+            }
+        }
+    })
+}
+
+// #[model(tree = "users", hooks, has_many(members = Member), key = "uuid_v7")];
+// por defecto el árbol es el nombre del struct en minúsculas. `hooks` registra
+// la implementación de ModelHooks, cada has_many genera un accesor a los hijos
+// y `key` fija la estrategia de Model::insert_auto. Con key = "snowflake",
+// `worker = <expr>` da el id del worker (u16 hasta 1023, 0 si falta); puede ser cualquier
+// expresión, p. ej. una función que lo lea de la configuración del shard.
+fn model_attrs(input: &DeriveInput) -> syn::Result<ModelAttrs> {
+    let mut tree = None;
+    let mut hooks = false;
+    let mut has_many = Vec::new();
+    let mut key_strategy = None;
+    let mut worker: Option<Expr> = None;
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("model")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tree") {
+                let value: LitStr = meta.value()?.parse()?;
+                tree = Some(value.value());
+                Ok(())
+            } else if meta.path.is_ident("hooks") {
+                hooks = true;
+                Ok(())
+            } else if meta.path.is_ident("has_many") {
+                meta.parse_nested_meta(|relation| {
+                    let accessor = relation.path.require_ident()?.clone();
+                    let child: Type = relation.value()?.parse()?;
+                    has_many.push((accessor, child));
+                    Ok(())
+                })
+            } else if meta.path.is_ident("key") {
+                let value: LitStr = meta.value()?.parse()?;
+                key_strategy = Some((value.value(), match value.value().as_str() {
+                    "sequence" => quote!(Sequence),
+                    "snowflake" => quote!(Snowflake),
+                    "uuid_v4" => quote!(UuidV4),
+                    "uuid_v7" => quote!(UuidV7),
+                    "ulid" => quote!(Ulid),
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "expected \"sequence\", \"snowflake\", \"uuid_v4\", \"uuid_v7\" or \"ulid\"",
+                        ))
+                    }
+                }));
+                Ok(())
+            } else if meta.path.is_ident("worker") {
+                worker = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error(
+                    "unsupported model attribute, expected `tree = \"...\"`, `hooks`, `has_many(...)`, `key = \"...\"` or `worker = ...`",
+                ))
+            }
+        })?;
+    }
+
+    let key_strategy = match (key_strategy, worker) {
+        (Some((name, strategy)), worker) if name == "snowflake" => {
+            let worker = worker.map_or_else(|| quote!(0), |worker| quote!(#worker));
+            Some(quote!(#strategy { worker: #worker }))
+        }
+        (_, Some(worker)) => {
+            return Err(syn::Error::new_spanned(worker, "`worker` requires key = \"snowflake\""));
+        }
+        (strategy, None) => strategy.map(|(_, strategy)| strategy),
+    };
+
+    Ok(ModelAttrs {
+        tree: tree.unwrap_or_else(|| input.ident.to_string().to_lowercase()),
+        hooks,
+        has_many,
+        key_strategy,
+    })
+}
+
+fn named_fields(input: &DeriveInput) -> syn::Result<Vec<&Field>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields.named.iter().collect()),
+            _ => Err(syn::Error::new_spanned(
+                &input.ident,
+                "Model can only be derived for structs with named fields",
+            )),
+        },
+        _ => Err(syn::Error::new_spanned(
+            &input.ident,
+            "Model can only be derived for structs",
+        )),
+    }
+}
+
+fn has_attr(field: &Field, name: &str) -> bool {
+    field.attrs.iter().any(|a| a.path().is_ident(name))
+}
+
+fn primary_key(input: &DeriveInput, fields: &[&Field]) -> syn::Result<PrimaryKey> {
+    let mut found = None;
+    for field in fields {
+        if !has_attr(field, "primary_key") {
+            continue;
+        }
+        if found.is_some() {
+            return Err(syn::Error::new_spanned(field, "only one field can be #[primary_key]"));
+        }
+        found = Some(PrimaryKey {
+            ident: field.ident.clone().expect("named field"),
+            ty: field.ty.clone(),
+        });
+    }
+
+    found.ok_or_else(|| {
+        syn::Error::new_spanned(&input.ident, "Model requires one field marked #[primary_key]")
+    })
+}
+
+// Cada campo #[index] o #[unique] se registra con el nombre del campo
+fn indexes(fields: &[&Field]) -> Vec<TokenStream2> {
+    fields
+        .iter()
+        .filter_map(|field| {
+            let ident = field.ident.as_ref()?;
+            let name = ident.to_string();
+            let method = if has_attr(field, "unique") {
+                quote!(create_unique_index)
+            } else if has_attr(field, "index") {
+                quote!(create_index)
+            } else {
+                return None;
+            };
+            Some(quote! {
+                tree.#method(#name, |model: &Self| ::core::clone::Clone::clone(&model.#ident))?;
+            })
+        })
+        .collect()
+}
+
+// El campo #[version] (u64) activa el bloqueo optimista del árbol
+fn version(fields: &[&Field]) -> syn::Result<Option<TokenStream2>> {
+    let mut found = None;
+    for field in fields.iter().filter(|field| has_attr(field, "version")) {
+        if found.is_some() {
+            return Err(syn::Error::new_spanned(field, "only one field can be #[version]"));
+        }
+        let ident = field.ident.as_ref().expect("named field");
+        found = Some(quote! {
+            tree.enable_versioning(|model: &Self| model.#ident, |model: &mut Self, version: u64| model.#ident = version);
+        });
+    }
+    Ok(found)
+}
+
+// #[belongs_to(Guild)] o #[belongs_to(Guild, name = "owner", on_delete = "cascade")];
+// sin `name` el accesor es el nombre del campo sin el sufijo `_id`. on_delete
+// puede ser "restrict" (por defecto), "cascade" o "set_null" (sólo en campos Option).
+fn belongs_to<'a>(fields: &[&'a Field]) -> syn::Result<Vec<BelongsTo<'a>>> {
+    let mut relations = Vec::new();
+    for field in fields {
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("belongs_to")) {
+            let (parent, name, on_delete) = attr.parse_args_with(|input: ParseStream| {
+                let parent: Type = input.parse()?;
+                let mut name = None;
+                let mut on_delete = None;
+                while input.parse::<Option<Token![,]>>()?.is_some() {
+                    let key: Ident = input.parse()?;
+                    input.parse::<Token![=]>()?;
+                    let value: LitStr = input.parse()?;
+                    if key == "name" {
+                        name = Some(value.parse::<Ident>()?);
+                    } else if key == "on_delete" {
+                        on_delete = Some(match value.value().as_str() {
+                            "restrict" => quote!(Restrict),
+                            "cascade" => quote!(Cascade),
+                            "set_null" if option_inner(&field.ty).is_some() => quote!(SetNull),
+                            "set_null" => {
+                                return Err(syn::Error::new_spanned(value, "on_delete = \"set_null\" needs an Option field"))
+                            }
+                            _ => {
+                                return Err(syn::Error::new_spanned(
+                                    value,
+                                    "expected \"restrict\", \"cascade\" or \"set_null\"",
+                                ))
+                            }
+                        });
+                    } else {
+                        return Err(syn::Error::new_spanned(key, "unsupported belongs_to option, expected `name` or `on_delete`"));
+                    }
+                }
+                Ok((parent, name, on_delete))
+            })?;
+
+            let ident = field.ident.as_ref().expect("named field");
+            let accessor = match name {
+                Some(name) => name,
+                None => match ident.to_string().strip_suffix("_id") {
+                    Some(stem) if !stem.is_empty() => Ident::new(stem, ident.span()),
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "the field name does not end in `_id`, add `name = \"...\"` for the accessor",
+                        ))
+                    }
+                },
+            };
+            relations.push(BelongsTo { field, parent, accessor, on_delete });
+        }
+    }
+    Ok(relations)
+}
+
+// Tipo interior de un campo Option<T>
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
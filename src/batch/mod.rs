@@ -0,0 +1,125 @@
+use serde::Serialize;
+use sled::transaction::ConflictableTransactionError;
+
+use crate::{OrmError, Tree};
+
+// Error de un elemento concreto dentro de un lote
+#[derive(Debug)]
+pub struct BatchItemError {
+    pub index: usize,
+    pub key: Vec<u8>,
+    pub error: OrmError,
+}
+
+// Acumula inserciones y borrados tipados y los aplica de forma atómica.
+// Si algún valor no se puede codificar o escribir no se aplica nada y apply
+// devuelve OrmError::Batch con el detalle de cada elemento.
+pub struct WriteBatch<'a> {
+    tree: &'a Tree,
+    ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    errors: Vec<BatchItemError>,
+}
+
+impl<'a> WriteBatch<'a> {
+    pub fn insert<K, V>(&mut self, key: K, value: &V) -> &mut Self
+    where
+        K: AsRef<[u8]>,
+        V: Serialize,
+    {
+        let key = key.as_ref().to_vec();
+        match self.tree.encode(&key, value) {
+            Ok(bytes) => self.ops.push((key, Some(bytes))),
+            Err(error) => self.errors.push(BatchItemError { index: self.ops.len() + self.errors.len(), key, error }),
+        }
+        self
+    }
+
+    pub fn remove<K: AsRef<[u8]>>(&mut self, key: K) -> &mut Self {
+        self.ops.push((key.as_ref().to_vec(), None));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len() + self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn apply(self) -> Result<(), OrmError> {
+        if !self.errors.is_empty() {
+            return Err(OrmError::Batch { tree: self.tree.name(), errors: self.errors });
+        }
+
+        // Con índices, caducidades o hooks cada operación tiene que pasar por
+        // TypedTx. Sin errores de codificación, la posición en `ops` es la del
+        // lote; los vetos de los hooks se acumulan como errores del lote.
+        let writing = self.tree.conn.registry.writing();
+        if self.tree.schema.needs_transaction() {
+            drop(writing);
+            return self.tree.transaction(|tx| {
+                let mut errors = Vec::new();
+                for (index, (key, value)) in self.ops.iter().enumerate() {
+                    match tx.write(key, value.as_deref()) {
+                        Ok(_) => {}
+                        Err(ConflictableTransactionError::Abort(error)) => {
+                            errors.push(BatchItemError { index, key: key.clone(), error })
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                match errors.is_empty() {
+                    true => Ok(()),
+                    false => tx.abort(OrmError::Batch { tree: self.tree.name(), errors }),
+                }
+            });
+        }
+
+        let mut batch = sled::Batch::default();
+        for (key, value) in self.ops {
+            match value {
+                Some(bytes) => batch.insert(key, bytes),
+                None => batch.remove(key),
+            }
+        }
+        self.tree.tree.apply_batch(batch)?;
+        Ok(())
+    }
+}
+
+impl Tree {
+    pub fn batch(&self) -> WriteBatch<'_> {
+        WriteBatch { tree: self, ops: Vec::new(), errors: Vec::new() }
+    }
+
+    // Inserta todos los pares en un único lote atómico; devuelve cuántos se escribieron
+    pub fn insert_many<K, V, I>(&self, items: I) -> Result<usize, OrmError>
+    where
+        K: AsRef<[u8]>,
+        V: Serialize,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut batch = self.batch();
+        for (key, value) in items {
+            batch.insert(key, &value);
+        }
+        let count = batch.len();
+        batch.apply()?;
+        Ok(count)
+    }
+
+    pub fn delete_many<K, I>(&self, keys: I) -> Result<usize, OrmError>
+    where
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = K>,
+    {
+        let mut batch = self.batch();
+        for key in keys {
+            batch.remove(key);
+        }
+        let count = batch.len();
+        batch.apply()?;
+        Ok(count)
+    }
+}
@@ -0,0 +1,225 @@
+use std::any::Any;
+use std::cell::RefCell;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sled::transaction::ConflictableTransactionError;
+
+use crate::{OrmError, Tree};
+
+// Registro con número de versión para bloqueo optimista: update_versioned
+// sólo escribe si la versión guardada coincide con la del registro leído. Si
+// el valor ya tiene su propia versión, ver Tree::enable_versioning.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Versioned<V> {
+    pub version: u64,
+    pub value: V,
+}
+
+impl<V> Versioned<V> {
+    // Versión 0: el registro todavía no existe
+    pub fn new(value: V) -> Self {
+        Versioned { version: 0, value }
+    }
+}
+
+type VersionOf = dyn Fn(&Tree, &[u8], &[u8]) -> Result<u64, OrmError> + Send + Sync;
+type WithVersion = dyn Fn(&Tree, &[u8], &[u8], u64) -> Result<Vec<u8>, OrmError> + Send + Sync;
+type Current = dyn Fn(&Tree, &[u8], &[u8]) -> Result<Box<dyn Any + Send + Sync>, OrmError> + Send + Sync;
+
+// Campo de versión de los registros de un árbol con bloqueo optimista (ver
+// Tree::enable_versioning); vive en su TreeSchema
+pub(crate) struct Versioning {
+    version: Box<VersionOf>,
+    with_version: Box<WithVersion>,
+    current: Box<Current>,
+}
+
+impl Versioning {
+    // Comprueba que `bytes` trae la versión de `stored` (0 si no hay registro)
+    // y devuelve el registro con la siguiente
+    pub(crate) fn next(&self, tree: &Tree, key: &[u8], stored: Option<&[u8]>, bytes: &[u8]) -> Result<Vec<u8>, OrmError> {
+        let found = stored.map(|stored| (self.version)(tree, key, stored)).transpose()?.unwrap_or(0);
+        if (self.version)(tree, key, bytes)? != found {
+            let current = stored.map(|stored| (self.current)(tree, key, stored)).transpose()?;
+            return Err(OrmError::Conflict { tree: tree.name(), key: key.to_vec(), current });
+        }
+        (self.with_version)(tree, key, bytes, found + 1)
+    }
+}
+
+impl Tree {
+    // Activa el bloqueo optimista: `get` lee la versión de un registro y `set`
+    // la cambia. Desde entonces cualquier escritura (insert, update, lotes,
+    // transacciones...) tiene que traer la versión guardada, 0 si el registro
+    // no existe, y se guarda con la siguiente; si no, falla con
+    // OrmError::Conflict con el valor guardado. El derive lo llama para el
+    // campo #[version]. Versioned y update_versioned son para árboles sin
+    // campo de versión.
+    pub fn enable_versioning<V, G, S>(&self, get: G, set: S)
+    where
+        V: DeserializeOwned + Serialize + Send + Sync + 'static,
+        G: Fn(&V) -> u64 + Send + Sync + 'static,
+        S: Fn(&mut V, u64) + Send + Sync + 'static,
+    {
+        let versioning = Versioning {
+            version: Box::new(move |tree, key, bytes| Ok(get(&tree.decode::<V>(key, bytes)?))),
+            with_version: Box::new(move |tree, key, bytes, version| {
+                let mut value: V = tree.decode(key, bytes)?;
+                set(&mut value, version);
+                tree.encode(key, &value)
+            }),
+            current: Box::new(|tree, key, bytes| Ok(Box::new(tree.decode::<V>(key, bytes)?))),
+        };
+        // Con las escrituras paradas, para que ninguna en curso se salte la comprobación
+        let _writes = self.conn.registry.changing_indexes();
+        self.schema.set_versioning(versioning);
+    }
+
+    // Lee el valor actual, deja que `decide` calcule el nuevo y lo escribe sólo
+    // si nadie lo cambió entretanto; si hubo contención, `decide` se repite con
+    // el valor más reciente
+    pub(crate) fn atomic_update<V, R, F>(&self, key: &[u8], decide: F) -> Result<R, OrmError>
+    where
+        V: DeserializeOwned,
+        F: Fn(Option<V>) -> Result<(Option<Vec<u8>>, R), OrmError>,
+    {
+        self.atomic_update_raw(key, |raw| {
+            let current = raw.map(|bytes| self.decode(key, bytes)).transpose()?;
+            decide(current)
+        })
+    }
+
+    // Igual que atomic_update pero con los bytes guardados, sin decodificar
+    pub(crate) fn atomic_update_raw<R, F>(&self, key: &[u8], decide: F) -> Result<R, OrmError>
+    where
+        F: Fn(Option<&[u8]>) -> Result<(Option<Vec<u8>>, R), OrmError>,
+    {
+        let writing = self.conn.registry.writing();
+        if self.schema.needs_transaction() {
+            drop(writing);
+            return self.transaction(|tx| {
+                let current = if tx.is_expired(key)? { None } else { tx.get_raw(key)? };
+                let (new, result) = decide(current.as_deref()).map_err(ConflictableTransactionError::Abort)?;
+                tx.write(key, new.as_deref())?;
+                Ok(result)
+            });
+        }
+
+        loop {
+            let raw = self.tree.get(key)?;
+            let (new, result) = decide(raw.as_deref())?;
+            if self.tree.compare_and_swap(key, raw, new)?.is_ok() {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn conflict<V>(&self, key: &[u8], current: Option<V>) -> OrmError
+    where
+        V: Send + Sync + 'static,
+    {
+        OrmError::Conflict {
+            tree: self.name(),
+            key: key.to_vec(),
+            current: current.map(|value| Box::new(value) as Box<dyn Any + Send + Sync>),
+        }
+    }
+
+    // Escribe `new` (o borra, si es None) sólo si el valor guardado es igual a
+    // `expected`; si no, devuelve OrmError::Conflict con el valor actual
+    pub fn compare_and_swap<K, V>(&self, key: K, expected: Option<&V>, new: Option<&V>) -> Result<(), OrmError>
+    where
+        K: AsRef<[u8]>,
+        V: Serialize + DeserializeOwned + PartialEq + Send + Sync + 'static,
+    {
+        let key = key.as_ref();
+        let new = new.map(|value| self.encode(key, value)).transpose()?;
+        self.atomic_update(key, |current: Option<V>| {
+            if current.as_ref() != expected {
+                return Err(self.conflict(key, current));
+            }
+            Ok((new.clone(), ()))
+        })
+    }
+
+    pub fn get_versioned<K, V>(&self, key: K) -> Result<Option<Versioned<V>>, OrmError>
+    where
+        K: AsRef<[u8]>,
+        V: DeserializeOwned,
+    {
+        self.get(key)
+    }
+
+    // Guarda `record.value` si la versión guardada sigue siendo `record.version`
+    // (0 si el registro no existe) y devuelve la nueva versión. Si otro escritor
+    // se adelantó, devuelve OrmError::Conflict con el Versioned<V> actual.
+    pub fn update_versioned<K, V>(&self, key: K, record: &Versioned<V>) -> Result<u64, OrmError>
+    where
+        K: AsRef<[u8]>,
+        V: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let key = key.as_ref();
+        self.atomic_update(key, |current: Option<Versioned<V>>| {
+            let found = current.as_ref().map_or(0, |c| c.version);
+            if found != record.version {
+                return Err(self.conflict(key, current));
+            }
+            let next = Versioned { version: found + 1, value: &record.value };
+            Ok((Some(self.encode(key, &next)?), next.version))
+        })
+    }
+
+    // Lee-modifica-escribe atómico: `f` recibe el valor actual (None si no
+    // existe) y devuelve el nuevo (None para borrar). Se reintenta si otro hilo
+    // escribe entretanto, así que `f` puede llamarse más de una vez.
+    // Devuelve (valor anterior, valor nuevo).
+    pub fn update_with<K, V, F>(&self, key: K, f: F) -> Result<(Option<V>, Option<V>), OrmError>
+    where
+        K: AsRef<[u8]>,
+        V: Serialize + DeserializeOwned + Clone,
+        F: FnMut(Option<V>) -> Option<V>,
+    {
+        let key = key.as_ref();
+        let f = RefCell::new(f);
+
+        // Con índices o caducidades la escritura tiene que pasar por la transacción
+        let writing = self.conn.registry.writing();
+        if self.schema.needs_transaction() {
+            drop(writing);
+            return self.atomic_update(key, |current: Option<V>| {
+                let new = (f.borrow_mut())(current.clone());
+                let bytes = new.as_ref().map(|value| self.encode(key, value)).transpose()?;
+                Ok((bytes, (current, new)))
+            });
+        }
+
+        // sled no deja devolver errores desde el closure: si algo falla se
+        // deja el valor como estaba y el error se devuelve al final
+        let mut error = None;
+        let mut new = None;
+        let old = self.tree.fetch_and_update(key, |raw| {
+            error = None;
+            let current = match raw.map(|bytes| self.decode::<V>(key, bytes)).transpose() {
+                Ok(current) => current,
+                Err(e) => {
+                    error = Some(e);
+                    return raw.map(|bytes| bytes.to_vec());
+                }
+            };
+            new = (f.borrow_mut())(current);
+            match new.as_ref().map(|value| self.encode(key, value)).transpose() {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    error = Some(e);
+                    raw.map(|bytes| bytes.to_vec())
+                }
+            }
+        })?;
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        let old = old.map(|bytes| self.decode(key, &bytes)).transpose()?;
+        Ok((old, new))
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{bincode_get_config, Connection, OrmError, Tree, ORM};
+
+pub type CodecError = Box<dyn std::error::Error + Send + Sync>;
+
+// Formato con el que se guardan los valores de un árbol. Es genérico, así que
+// no se puede usar como objeto: los formatos propios implementan ByteCodec
+pub(crate) trait Codec {
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> Result<Vec<u8>, CodecError>;
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> Result<V, CodecError>;
+}
+
+// Compacto pero no autodescriptivo: añadir un campo rompe los registros viejos
+pub(crate) struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> Result<Vec<u8>, CodecError> {
+        Ok(bincode::serde::encode_to_vec(value, bincode_get_config())?)
+    }
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> Result<V, CodecError> {
+        // decode_from_slice devuelve (T, usize), extraer solo el valor
+        Ok(bincode::serde::decode_from_slice(bytes, bincode_get_config()).map(|(value, _)| value)?)
+    }
+}
+
+#[cfg(feature = "json")]
+pub(crate) struct Json;
+
+#[cfg(feature = "json")]
+impl Codec for Json {
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> Result<Vec<u8>, CodecError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> Result<V, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+// Los structs se guardan como mapas (con nombres de campo) para que sigan
+// siendo legibles si cambia el orden o se añaden campos con #[serde(default)]
+#[cfg(feature = "msgpack")]
+pub(crate) struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePack {
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> Result<Vec<u8>, CodecError> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> Result<V, CodecError> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+#[cfg(feature = "cbor")]
+pub(crate) struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> Result<Vec<u8>, CodecError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> Result<V, CodecError> {
+        Ok(ciborium::from_reader(bytes)?)
+    }
+}
+
+// Formato propio, se elige con CodecKind::custom. Trabaja sobre los bytes que
+// produce `base` (p. ej. para añadir una cabecera o pasarlos a otro formato)
+pub trait ByteCodec: Send + Sync {
+    fn base(&self) -> CodecKind {
+        CodecKind::Bincode
+    }
+
+    fn encode(&self, bytes: Vec<u8>) -> Result<Vec<u8>, CodecError>;
+
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>, CodecError>;
+}
+
+// Codec elegido para una Connection o un árbol concreto
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub enum CodecKind {
+    #[default]
+    Bincode,
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    Custom(Arc<dyn ByteCodec>),
+}
+
+impl CodecKind {
+    pub fn custom(codec: impl ByteCodec + 'static) -> Self {
+        CodecKind::Custom(Arc::new(codec))
+    }
+}
+
+impl std::fmt::Debug for CodecKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecKind::Bincode => f.write_str("Bincode"),
+            #[cfg(feature = "json")]
+            CodecKind::Json => f.write_str("Json"),
+            #[cfg(feature = "msgpack")]
+            CodecKind::MessagePack => f.write_str("MessagePack"),
+            #[cfg(feature = "cbor")]
+            CodecKind::Cbor => f.write_str("Cbor"),
+            CodecKind::Custom(codec) => f.debug_tuple("Custom").field(&codec.base()).finish(),
+        }
+    }
+}
+
+// Dos Custom son iguales sólo si son el mismo objeto
+impl PartialEq for CodecKind {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (CodecKind::Custom(a), CodecKind::Custom(b)) => Arc::ptr_eq(a, b),
+            (CodecKind::Custom(_), _) | (_, CodecKind::Custom(_)) => false,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for CodecKind {}
+
+impl Codec for CodecKind {
+    fn encode<V: Serialize + ?Sized>(&self, value: &V) -> Result<Vec<u8>, CodecError> {
+        match self {
+            CodecKind::Bincode => Bincode.encode(value),
+            #[cfg(feature = "json")]
+            CodecKind::Json => Json.encode(value),
+            #[cfg(feature = "msgpack")]
+            CodecKind::MessagePack => MessagePack.encode(value),
+            #[cfg(feature = "cbor")]
+            CodecKind::Cbor => Cbor.encode(value),
+            CodecKind::Custom(codec) => codec.encode(codec.base().encode(value)?),
+        }
+    }
+
+    fn decode<V: DeserializeOwned>(&self, bytes: &[u8]) -> Result<V, CodecError> {
+        match self {
+            CodecKind::Bincode => Bincode.decode(bytes),
+            #[cfg(feature = "json")]
+            CodecKind::Json => Json.decode(bytes),
+            #[cfg(feature = "msgpack")]
+            CodecKind::MessagePack => MessagePack.decode(bytes),
+            #[cfg(feature = "cbor")]
+            CodecKind::Cbor => Cbor.decode(bytes),
+            CodecKind::Custom(codec) => codec.base().decode(&codec.decode(bytes)?),
+        }
+    }
+}
+
+impl Connection {
+    // Codec por defecto de los árboles abiertos desde esta conexión
+    pub fn with_codec(mut self, codec: CodecKind) -> Self {
+        self.codec = codec;
+        self
+    }
+}
+
+impl ORM {
+    // Abre el árbol y fija su codec; las siguientes llamadas a orm.tree(name)
+    // sobre esta conexión lo usan también
+    pub fn tree_with_codec(&self, name: &str, codec: CodecKind) -> Result<Tree, OrmError> {
+        self.conn.registry.schema(name).set_codec(codec);
+        self.tree(name)
+    }
+}
+
+impl Tree {
+    pub fn codec(&self) -> CodecKind {
+        self.schema.codec().unwrap_or_else(|| self.conn.codec.clone())
+    }
+}
@@ -0,0 +1,114 @@
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Cursor, Key, OrmError, Page, Query, Subscription, Tree, TypedIter};
+
+// Vista tipada de un Tree: todas las operaciones usan el mismo K y V
+pub struct Collection<K, V> {
+    pub tree: Tree,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> Collection<K, V>
+where
+    K: Key,
+    V: Serialize + DeserializeOwned,
+{
+    pub(crate) fn new(tree: Tree) -> Self {
+        Collection { tree, marker: PhantomData }
+    }
+
+    pub fn insert(&self, key: &K, value: &V) -> Result<(), OrmError> {
+        self.tree.insert(key.to_key_bytes(), value)
+    }
+
+    pub fn get(&self, key: &K) -> Result<Option<V>, OrmError> {
+        self.tree.get(key.to_key_bytes())
+    }
+
+    pub fn update(&self, key: &K, value: &V) -> Result<(), OrmError> {
+        self.tree.update(key.to_key_bytes(), value)
+    }
+
+    pub fn delete(&self, key: &K) -> Result<(), OrmError> {
+        self.tree.delete(key.to_key_bytes())
+    }
+
+    pub fn contains(&self, key: &K) -> Result<bool, OrmError> {
+        let key = key.to_key_bytes();
+        Ok(self.tree.tree.contains_key(&key)? && !self.tree.is_expired(&key)?)
+    }
+
+    pub fn find<F>(&self, predicate: F) -> Result<Vec<(K, V)>, OrmError>
+    where
+        F: Fn(&V) -> bool,
+    {
+        self.iter()
+            .filter(|item| item.as_ref().map_or(true, |(_, value)| predicate(value)))
+            .collect()
+    }
+
+    pub fn create_index<I, F>(&self, name: &str, f: F) -> Result<(), OrmError>
+    where
+        I: Key,
+        F: Fn(&V) -> I + Send + Sync + 'static,
+    {
+        self.tree.create_index(name, f)
+    }
+
+    pub fn create_unique_index<I, F>(&self, name: &str, f: F) -> Result<(), OrmError>
+    where
+        I: Key,
+        F: Fn(&V) -> I + Send + Sync + 'static,
+    {
+        self.tree.create_unique_index(name, f)
+    }
+
+    pub fn find_by_index<I: Key>(&self, name: &str, value: &I) -> Result<Vec<(K, V)>, OrmError> {
+        let mut results = Vec::new();
+        for key in self.tree.find_keys_by_index(name, value)? {
+            if let Some(value) = self.tree.get(&key)? {
+                results.push((self.tree.decode_key(&key)?, value));
+            }
+        }
+        Ok(results)
+    }
+
+    pub fn iter(&self) -> TypedIter<'_, K, V> {
+        TypedIter::new(&self.tree, self.tree.tree.iter())
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> TypedIter<'_, K, V> {
+        self.tree.range(range)
+    }
+
+    pub fn prefix<P: AsRef<[u8]>>(&self, prefix: P) -> TypedIter<'_, K, V> {
+        TypedIter::new(&self.tree, self.tree.tree.scan_prefix(prefix))
+    }
+
+    pub fn subscribe<P: AsRef<[u8]>>(&self, prefix: P) -> Subscription<K, V> {
+        self.tree.subscribe_as(prefix)
+    }
+
+    pub fn page(&self, cursor: Option<Cursor>, page_size: usize) -> Result<Page<K, V>, OrmError> {
+        self.tree.page_as(cursor, page_size)
+    }
+
+    pub fn query(&self) -> Query<'_, V> {
+        self.tree.query()
+    }
+
+    pub fn all(&self) -> Result<Vec<(K, V)>, OrmError> {
+        self.find(|_| true)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.tree.is_empty()
+    }
+}
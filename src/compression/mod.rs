@@ -0,0 +1,100 @@
+use std::borrow::Cow;
+
+use crate::{CodecError, OrmError, Tree, ORM};
+
+// Primer byte de cada valor en un árbol comprimido
+const RAW: u8 = 0;
+const ZSTD: u8 = 1;
+const LZ4: u8 = 2;
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+const DEFAULT_THRESHOLD: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+// Compresión de un árbol, aplicada después de serializar. Los valores más
+// pequeños que `threshold` se guardan sin comprimir; una cabecera de un byte
+// indica cómo está guardado cada valor, así que cambiar de algoritmo o de
+// umbral no invalida los registros existentes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    algorithm: Algorithm,
+    threshold: usize,
+}
+
+impl Compression {
+    #[cfg(feature = "zstd")]
+    pub fn zstd(level: i32) -> Self {
+        Compression { algorithm: Algorithm::Zstd(level), threshold: DEFAULT_THRESHOLD }
+    }
+
+    #[cfg(feature = "lz4")]
+    pub fn lz4() -> Self {
+        Compression { algorithm: Algorithm::Lz4, threshold: DEFAULT_THRESHOLD }
+    }
+
+    // Tamaño mínimo en bytes (ya serializado) a partir del cual se comprime
+    pub fn threshold(mut self, bytes: usize) -> Self {
+        self.threshold = bytes;
+        self
+    }
+
+    pub(crate) fn compress(&self, bytes: Vec<u8>) -> Result<Vec<u8>, CodecError> {
+        let (header, body) = if bytes.len() < self.threshold {
+            (RAW, bytes)
+        } else {
+            match self.algorithm {
+                #[cfg(feature = "zstd")]
+                Algorithm::Zstd(level) => (ZSTD, zstd::bulk::compress(&bytes, level)?),
+                #[cfg(feature = "lz4")]
+                Algorithm::Lz4 => (LZ4, lz4_flex::compress_prepend_size(&bytes)),
+            }
+        };
+        let mut stored = Vec::with_capacity(body.len() + 1);
+        stored.push(header);
+        stored.extend_from_slice(&body);
+        Ok(stored)
+    }
+
+    // No depende del algoritmo configurado: se guía por la cabecera
+    pub(crate) fn decompress(stored: &[u8]) -> Result<Cow<'_, [u8]>, CodecError> {
+        let Some((&header, body)) = stored.split_first() else {
+            return Err("compressed value is missing its header byte".into());
+        };
+        match header {
+            RAW => Ok(Cow::Borrowed(body)),
+            #[cfg(feature = "zstd")]
+            ZSTD => Ok(Cow::Owned(zstd::stream::decode_all(body)?)),
+            #[cfg(feature = "lz4")]
+            LZ4 => Ok(Cow::Owned(lz4_flex::decompress_size_prepended(body)?)),
+            #[cfg(not(feature = "zstd"))]
+            ZSTD => Err("value is zstd-compressed but the `zstd` feature is disabled".into()),
+            #[cfg(not(feature = "lz4"))]
+            LZ4 => Err("value is lz4-compressed but the `lz4` feature is disabled".into()),
+            _ => Err(format!("unknown compression header {}", header).into()),
+        }
+    }
+}
+
+impl ORM {
+    // Abre el árbol con compresión; igual que tree_with_codec, la configuración
+    // queda fijada para el árbol en esta conexión. Los valores que ya hubiera
+    // sin cabecera dejan de poder leerse, así que conviene activarla en
+    // árboles nuevos o mediante una migración.
+    pub fn tree_with_compression(&self, name: &str, compression: Compression) -> Result<Tree, OrmError> {
+        self.conn.registry.schema(name).set_compression(compression);
+        self.tree(name)
+    }
+}
+
+impl Tree {
+    pub fn compression(&self) -> Option<Compression> {
+        self.schema.compression()
+    }
+}
@@ -2,12 +2,17 @@
 
 use sled::Db;
 
-use crate::{Connection, ORM};
+use crate::{Connection, OrmError, ORM};
 
 impl Connection {
-    pub fn new(path: &str) -> Result<Self, sled::Error> {
+    pub fn new(path: &str) -> Result<Self, OrmError> {
         let db = sled::open(path)?;
-        Ok(Connection { db })
+        Ok(Connection {
+            db,
+            registry: Default::default(),
+            codec: Default::default(),
+            keyring: Default::default(),
+        })
     }
 
     pub fn get_instance(&self) -> &Db {
@@ -15,6 +20,6 @@ impl Connection {
     }
 
     pub fn get_orm(&self) -> ORM {
-        ORM { conn: Connection { db: self.db.clone() } }
+        ORM { conn: self.clone() }
     }
 }
\ No newline at end of file
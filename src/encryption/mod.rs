@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+
+use crate::{CodecError, Connection, OrmError, Tree, ORM};
+
+#[cfg(feature = "aes-gcm")]
+use aes_gcm::{aead, Aes256Gcm};
+#[cfg(feature = "chacha20")]
+use chacha20poly1305::ChaCha20Poly1305;
+#[cfg(all(feature = "chacha20", not(feature = "aes-gcm")))]
+use chacha20poly1305::aead;
+#[cfg(any(feature = "aes-gcm", feature = "chacha20"))]
+use self::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+
+// Primer byte del sobre cifrado: algoritmo usado
+#[cfg(feature = "aes-gcm")]
+const AES_GCM: u8 = 1;
+#[cfg(feature = "chacha20")]
+const CHACHA20: u8 = 2;
+const NONCE_LEN: usize = 12;
+
+#[derive(Clone)]
+enum Cipher {
+    #[cfg(feature = "aes-gcm")]
+    AesGcm(Box<Aes256Gcm>),
+    #[cfg(feature = "chacha20")]
+    ChaCha20(Box<ChaCha20Poly1305>),
+}
+
+// Cifrado de los valores de un árbol, aplicado después de serializar y
+// comprimir. Cada registro lleva su propio nonce aleatorio y la clave del
+// registro va como dato asociado, así que copiar un valor cifrado a otra
+// clave también se detecta al leerlo.
+#[derive(Clone)]
+pub struct Encryption {
+    cipher: Cipher,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Nunca mostrar la clave
+        f.debug_struct("Encryption").field("algorithm", &self.algorithm()).finish()
+    }
+}
+
+impl Encryption {
+    #[cfg(feature = "aes-gcm")]
+    pub fn aes_gcm(key: &[u8; 32]) -> Self {
+        Encryption { cipher: Cipher::AesGcm(Box::new(Aes256Gcm::new(key.into()))) }
+    }
+
+    #[cfg(feature = "chacha20")]
+    pub fn chacha20(key: &[u8; 32]) -> Self {
+        Encryption { cipher: Cipher::ChaCha20(Box::new(ChaCha20Poly1305::new(key.into()))) }
+    }
+
+    pub fn algorithm(&self) -> &'static str {
+        match self.cipher {
+            #[cfg(feature = "aes-gcm")]
+            Cipher::AesGcm(_) => "aes-256-gcm",
+            #[cfg(feature = "chacha20")]
+            Cipher::ChaCha20(_) => "chacha20-poly1305",
+        }
+    }
+
+    // Sin ningún algoritmo activado no existe ningún Encryption que llamar
+    #[cfg_attr(not(any(feature = "aes-gcm", feature = "chacha20")), allow(unused_variables))]
+    pub(crate) fn encrypt(&self, key: &[u8], plain: &[u8]) -> Result<Vec<u8>, CodecError> {
+        match self.cipher {
+            #[cfg(feature = "aes-gcm")]
+            Cipher::AesGcm(ref cipher) => seal(cipher.as_ref(), AES_GCM, key, plain),
+            #[cfg(feature = "chacha20")]
+            Cipher::ChaCha20(ref cipher) => seal(cipher.as_ref(), CHACHA20, key, plain),
+        }
+    }
+
+    // None si el sobre está truncado, es de otro algoritmo o no se autentica
+    #[cfg_attr(not(any(feature = "aes-gcm", feature = "chacha20")), allow(unused_variables))]
+    pub(crate) fn decrypt(&self, key: &[u8], envelope: &[u8]) -> Option<Vec<u8>> {
+        if envelope.len() < 1 + NONCE_LEN {
+            return None;
+        }
+        match self.cipher {
+            #[cfg(feature = "aes-gcm")]
+            Cipher::AesGcm(ref cipher) => open(cipher.as_ref(), AES_GCM, key, envelope),
+            #[cfg(feature = "chacha20")]
+            Cipher::ChaCha20(ref cipher) => open(cipher.as_ref(), CHACHA20, key, envelope),
+        }
+    }
+}
+
+// Sobre: algoritmo ++ nonce ++ texto cifrado con su etiqueta
+#[cfg(any(feature = "aes-gcm", feature = "chacha20"))]
+fn seal<C: Aead + AeadCore>(cipher: &C, algorithm: u8, key: &[u8], plain: &[u8]) -> Result<Vec<u8>, CodecError> {
+    let nonce = C::generate_nonce(&mut OsRng);
+    let sealed = cipher
+        .encrypt(&nonce, Payload { msg: plain, aad: key })
+        .map_err(|_| "failed to encrypt value")?;
+
+    let mut envelope = Vec::with_capacity(1 + nonce.len() + sealed.len());
+    envelope.push(algorithm);
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&sealed);
+    Ok(envelope)
+}
+
+#[cfg(any(feature = "aes-gcm", feature = "chacha20"))]
+fn open<C: Aead + AeadCore>(cipher: &C, algorithm: u8, key: &[u8], envelope: &[u8]) -> Option<Vec<u8>> {
+    if envelope[0] != algorithm {
+        return None;
+    }
+    let (nonce, sealed) = envelope[1..].split_at(NONCE_LEN);
+    cipher.decrypt(nonce.into(), Payload { msg: sealed, aad: key }).ok()
+}
+
+// Claves de cifrado identificadas por un número. Cada valor guarda el id de
+// la clave con la que se cifró, así que se puede seguir leyendo con claves
+// viejas mientras se cifra siempre con la actual.
+#[derive(Default)]
+pub struct Keyring {
+    keys: RwLock<BTreeMap<u32, Encryption>>,
+    current: RwLock<Option<u32>>,
+}
+
+impl Keyring {
+    fn single(encryption: Encryption) -> Self {
+        let keyring = Keyring::default();
+        keyring.set_current_key(0, encryption);
+        keyring
+    }
+
+    // Clave que sólo se usa para descifrar valores antiguos
+    pub fn add_key(&self, id: u32, encryption: Encryption) {
+        self.keys.write().unwrap().insert(id, encryption);
+    }
+
+    // Añade la clave y la usa para todo lo que se escriba a partir de ahora
+    pub fn set_current_key(&self, id: u32, encryption: Encryption) {
+        self.add_key(id, encryption);
+        *self.current.write().unwrap() = Some(id);
+    }
+
+    pub fn current_key_id(&self) -> Option<u32> {
+        *self.current.read().unwrap()
+    }
+
+    // Id de la clave con la que se cifró un valor guardado
+    pub fn key_id_of(stored: &[u8]) -> Option<u32> {
+        Some(u32::from_be_bytes(stored.get(..4)?.try_into().ok()?))
+    }
+
+    // Sobre: id de la clave ++ sobre de Encryption
+    pub(crate) fn encrypt(&self, key: &[u8], plain: &[u8]) -> Result<Vec<u8>, CodecError> {
+        let id = self.current_key_id().ok_or("keyring has no current key")?;
+        let keys = self.keys.read().unwrap();
+        let mut envelope = id.to_be_bytes().to_vec();
+        envelope.extend(keys[&id].encrypt(key, plain)?);
+        Ok(envelope)
+    }
+
+    pub(crate) fn decrypt(&self, key: &[u8], envelope: &[u8]) -> Option<Vec<u8>> {
+        let id = Self::key_id_of(envelope)?;
+        self.keys.read().unwrap().get(&id)?.decrypt(key, &envelope[4..])
+    }
+}
+
+impl Connection {
+    // Keyring compartido por los árboles abiertos con tree_with_keyring
+    pub fn keyring(&self) -> &Keyring {
+        &self.keyring
+    }
+}
+
+impl ORM {
+    // Abre el árbol con cifrado; la configuración queda fijada para el árbol
+    // en esta conexión, como en tree_with_codec. Los índices secundarios
+    // guardan el valor indexado sin cifrar.
+    pub fn tree_with_encryption(&self, name: &str, encryption: Encryption) -> Result<Tree, OrmError> {
+        self.conn.registry.schema(name).set_encryption(Arc::new(Keyring::single(encryption)));
+        self.tree(name)
+    }
+
+    // Igual, pero cifrando con las claves del keyring de la conexión
+    pub fn tree_with_keyring(&self, name: &str) -> Result<Tree, OrmError> {
+        self.conn.registry.schema(name).set_encryption(self.conn.keyring.clone());
+        self.tree(name)
+    }
+}
+
+impl Tree {
+    pub fn is_encrypted(&self) -> bool {
+        self.schema.encryption().is_some()
+    }
+
+    // Vuelve a cifrar con la clave actual todos los valores cifrados con otra.
+    // Cada registro se reemplaza con compare_and_swap, así que las lecturas y
+    // escrituras concurrentes siguen funcionando; los valores que ya usan la
+    // clave actual se saltan, de modo que si se interrumpe basta con volver a
+    // llamarla. Devuelve cuántos registros se cifraron de nuevo.
+    pub fn rotate_keys(&self) -> Result<usize, OrmError> {
+        let Some(keyring) = self.schema.encryption() else {
+            return Ok(0);
+        };
+        let Some(current) = keyring.current_key_id() else {
+            return Ok(0);
+        };
+
+        let mut rotated = 0;
+        for item in self.tree.iter() {
+            let (key, stored) = item?;
+            if Keyring::key_id_of(&stored) == Some(current) {
+                continue;
+            }
+            let plain = keyring.decrypt(&key, &stored).ok_or_else(|| OrmError::Decrypt {
+                tree: self.name(),
+                key: key.to_vec(),
+            })?;
+            let sealed = keyring.encrypt(&key, &plain).map_err(|source| OrmError::Encode {
+                tree: self.name(),
+                type_name: std::any::type_name::<[u8]>(),
+                source,
+            })?;
+            // Si alguien escribió entretanto, su valor ya va con la clave actual
+            if self.tree.compare_and_swap(&key, Some(&stored), Some(sealed))?.is_ok() {
+                rotated += 1;
+            }
+        }
+        Ok(rotated)
+    }
+
+    pub fn rotate_keys_in_background(&self) -> JoinHandle<Result<usize, OrmError>> {
+        let tree = self.clone();
+        std::thread::spawn(move || tree.rotate_keys())
+    }
+}
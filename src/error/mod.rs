@@ -0,0 +1,253 @@
+use std::any::Any;
+use std::fmt;
+
+use sled::transaction::TransactionError;
+
+use crate::{BatchItemError, BlockingReference, CodecError};
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OrmError {
+    // Error de E/S o corrupción reportado por sled
+    Sled(sled::Error),
+    Encode {
+        tree: String,
+        type_name: &'static str,
+        source: CodecError,
+    },
+    Decode {
+        tree: String,
+        key: Vec<u8>,
+        type_name: &'static str,
+        source: CodecError,
+    },
+    // La clave guardada no se puede convertir al tipo de clave pedido
+    InvalidKey {
+        tree: String,
+        key: Vec<u8>,
+        type_name: &'static str,
+    },
+    NotFound {
+        tree: String,
+        key: Vec<u8>,
+    },
+    UnknownIndex {
+        tree: String,
+        index: String,
+    },
+    // El mismo árbol se pasó dos veces a ORM::transaction
+    DuplicateTree {
+        tree: String,
+    },
+    // El valor guardado no era el esperado; `current` es el valor actual
+    // (ver OrmError::current_value)
+    Conflict {
+        tree: String,
+        key: Vec<u8>,
+        current: Option<Box<dyn Any + Send + Sync>>,
+    },
+    // Otro registro ya tiene reservado el valor de un índice único
+    UniqueViolation {
+        tree: String,
+        field: String,
+        existing_key: Vec<u8>,
+    },
+    // Elementos de un lote que no se pudieron codificar o escribir (vetos de
+    // hooks, índices únicos...); el lote no se aplicó
+    Batch {
+        tree: String,
+        errors: Vec<BatchItemError>,
+    },
+    // El árbol está en `version` y no hay ningún paso registrado desde ella
+    MissingMigration {
+        tree: String,
+        version: u64,
+    },
+    // El valor cifrado no se pudo autenticar: fue modificado, movido a otra
+    // clave o se cifró con otra clave
+    Decrypt {
+        tree: String,
+        key: Vec<u8>,
+    },
+    // Otros registros apuntan a `key` con OnDelete::Restrict; no se borró nada
+    ForeignKeyViolation {
+        tree: String,
+        key: Vec<u8>,
+        references: Vec<BlockingReference>,
+    },
+    // `foreign_key` de `key` apunta a un registro de `parent_tree` que no
+    // existe (o ha caducado); no se escribió nada
+    MissingParent {
+        tree: String,
+        key: Vec<u8>,
+        foreign_key: String,
+        parent_tree: String,
+        parent_key: Vec<u8>,
+    },
+    // Hay hijos en `tree` que borrar o desvincular pero su modelo no se ha
+    // configurado en esta conexión, así que no se pueden mantener sus índices
+    UnloadedRelation {
+        tree: String,
+        foreign_key: String,
+    },
+    // Token de paginación corrupto o de otra versión
+    InvalidCursor(String),
+    // Tree::page con page_size 0
+    InvalidPageSize,
+    // KeyStrategy::Snowflake con un worker que no cabe en sus 10 bits
+    InvalidWorker(u16),
+    // Transacción abortada por el usuario
+    Aborted(String),
+}
+
+impl OrmError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, OrmError::NotFound { .. })
+    }
+
+    // Valor guardado que provocó un Conflict, con el tipo usado en la operación
+    pub fn current_value<V: 'static>(&self) -> Option<&V> {
+        match self {
+            OrmError::Conflict { current: Some(current), .. } => current.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, OrmError::Conflict { .. } | OrmError::UniqueViolation { .. })
+    }
+}
+
+pub(crate) fn display_key(key: &[u8]) -> String {
+    String::from_utf8_lossy(key).into_owned()
+}
+
+impl fmt::Display for OrmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrmError::Sled(e) => write!(f, "sled error: {}", e),
+            OrmError::Encode { tree, type_name, source } => {
+                write!(f, "failed to encode {} for tree `{}`: {}", type_name, tree, source)
+            }
+            OrmError::Decode { tree, key, type_name, source } => write!(
+                f,
+                "failed to decode {} at key `{}` in tree `{}`: {}",
+                type_name,
+                display_key(key),
+                tree,
+                source
+            ),
+            OrmError::InvalidKey { tree, key, type_name } => write!(
+                f,
+                "key `{}` in tree `{}` is not a valid {}",
+                display_key(key),
+                tree,
+                type_name
+            ),
+            OrmError::NotFound { tree, key } => {
+                write!(f, "key `{}` not found in tree `{}`", display_key(key), tree)
+            }
+            OrmError::UnknownIndex { tree, index } => {
+                write!(f, "tree `{}` has no index named `{}`", tree, index)
+            }
+            OrmError::DuplicateTree { tree } => {
+                write!(f, "tree `{}` appears more than once in the transaction", tree)
+            }
+            OrmError::Conflict { tree, key, .. } => {
+                write!(f, "conflicting write on key `{}` in tree `{}`", display_key(key), tree)
+            }
+            OrmError::UniqueViolation { tree, field, existing_key } => write!(
+                f,
+                "unique constraint `{}` in tree `{}` already used by key `{}`",
+                field,
+                tree,
+                display_key(existing_key)
+            ),
+            OrmError::Batch { tree, errors } => {
+                write!(f, "batch for tree `{}` rejected, {} item(s) failed", tree, errors.len())?;
+                if let Some(first) = errors.first() {
+                    write!(f, "; item {} (`{}`): {}", first.index, display_key(&first.key), first.error)?;
+                }
+                Ok(())
+            }
+            OrmError::MissingMigration { tree, version } => {
+                write!(f, "tree `{}` is at schema version {} but no migration starts there", tree, version)
+            }
+            OrmError::Decrypt { tree, key } => write!(
+                f,
+                "value at key `{}` in tree `{}` failed authentication",
+                display_key(key),
+                tree
+            ),
+            OrmError::ForeignKeyViolation { tree, key, references } => {
+                write!(
+                    f,
+                    "key `{}` in tree `{}` is still referenced by {} record(s)",
+                    display_key(key),
+                    tree,
+                    references.len()
+                )?;
+                if let Some(first) = references.first() {
+                    write!(f, "; first: `{}` in tree `{}` via `{}`", display_key(&first.key), first.tree, first.foreign_key)?;
+                }
+                Ok(())
+            }
+            OrmError::MissingParent { tree, key, foreign_key, parent_tree, parent_key } => write!(
+                f,
+                "`{}` of key `{}` in tree `{}` points to missing key `{}` in tree `{}`",
+                foreign_key,
+                display_key(key),
+                tree,
+                display_key(parent_key),
+                parent_tree
+            ),
+            OrmError::UnloadedRelation { tree, foreign_key } => write!(
+                f,
+                "records in tree `{}` reference the deleted key through `{}` but its model is not configured",
+                tree, foreign_key
+            ),
+            OrmError::InvalidCursor(token) => write!(f, "invalid page cursor `{}`", token),
+            OrmError::InvalidPageSize => write!(f, "page size must be at least 1"),
+            OrmError::InvalidWorker(worker) => write!(f, "snowflake worker id {} is above 1023", worker),
+            OrmError::Aborted(reason) => write!(f, "transaction aborted: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for OrmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OrmError::Sled(e) => Some(e),
+            OrmError::Encode { source, .. } => Some(source.as_ref()),
+            OrmError::Decode { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<sled::Error> for OrmError {
+    fn from(error: sled::Error) -> Self {
+        OrmError::Sled(error)
+    }
+}
+
+impl From<TransactionError<OrmError>> for OrmError {
+    fn from(error: TransactionError<OrmError>) -> Self {
+        match error {
+            TransactionError::Abort(e) => e,
+            TransactionError::Storage(e) => OrmError::Sled(e),
+        }
+    }
+}
+
+impl From<String> for OrmError {
+    fn from(reason: String) -> Self {
+        OrmError::Aborted(reason)
+    }
+}
+
+impl From<&str> for OrmError {
+    fn from(reason: &str) -> Self {
+        OrmError::Aborted(reason.to_string())
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Model, OrmError, Tree};
+
+type BeforeSave = dyn Fn(&Tree, &[u8], Vec<u8>) -> Result<Vec<u8>, OrmError> + Send + Sync;
+type Observer = dyn Fn(&Tree, &[u8], &[u8]) -> Result<(), OrmError> + Send + Sync;
+
+// Hooks de un árbol. Trabajan sobre los bytes ya codificados: cada hook
+// decodifica el valor con su propio tipo, así Tree::insert no necesita
+// saber qué hooks hay ni de qué tipo son. Se guardan con nombre para que
+// registrar dos veces el mismo (p. ej. al volver a abrir el árbol) no lo duplique.
+// Los ejecuta TypedTx::write, así que valen para cualquier escritura: insert,
+// update, delete, lotes, update_with, compare_and_swap, insert_with_ttl,
+// transacciones, borrados en cascada y purge_expired. Sólo las migraciones
+// reescriben registros sin pasar por ellos.
+#[derive(Default, Clone)]
+pub(crate) struct Hooks {
+    before_save: Vec<(String, Arc<BeforeSave>)>,
+    after_save: Vec<(String, Arc<Observer>)>,
+    before_delete: Vec<(String, Arc<Observer>)>,
+    after_delete: Vec<(String, Arc<Observer>)>,
+}
+
+fn add<T: ?Sized>(hooks: &mut Vec<(String, Arc<T>)>, name: &str, hook: Arc<T>) {
+    if !hooks.iter().any(|(existing, _)| existing == name) {
+        hooks.push((name.to_string(), hook));
+    }
+}
+
+impl Hooks {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.before_save.is_empty()
+            && self.after_save.is_empty()
+            && self.before_delete.is_empty()
+            && self.after_delete.is_empty()
+    }
+
+    pub(crate) fn before_save(&self, tree: &Tree, key: &[u8], mut bytes: Vec<u8>) -> Result<Vec<u8>, OrmError> {
+        for (_, hook) in &self.before_save {
+            bytes = hook(tree, key, bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    pub(crate) fn after_save(&self, tree: &Tree, key: &[u8], bytes: &[u8]) -> Result<(), OrmError> {
+        self.after_save.iter().try_for_each(|(_, hook)| hook(tree, key, bytes))
+    }
+
+    pub(crate) fn before_delete(&self, tree: &Tree, key: &[u8], bytes: &[u8]) -> Result<(), OrmError> {
+        self.before_delete.iter().try_for_each(|(_, hook)| hook(tree, key, bytes))
+    }
+
+    pub(crate) fn after_delete(&self, tree: &Tree, key: &[u8], bytes: &[u8]) -> Result<(), OrmError> {
+        self.after_delete.iter().try_for_each(|(_, hook)| hook(tree, key, bytes))
+    }
+}
+
+// Hooks de un modelo; se activan con #[model(hooks)] en el derive o con
+// Tree::use_model_hooks. before_save recibe una copia del valor, así que los
+// cambios se guardan pero no se reflejan en el `self` que se pasó a save.
+pub trait ModelHooks: Model {
+    fn before_save(&mut self) -> Result<(), OrmError> {
+        Ok(())
+    }
+
+    fn after_save(&self) {}
+
+    fn before_delete(&self) -> Result<(), OrmError> {
+        Ok(())
+    }
+
+    fn after_delete(&self) {}
+}
+
+impl Tree {
+    // `hook` puede modificar el valor antes de guardarlo o vetar la escritura
+    pub fn before_save<V, F>(&self, name: &str, hook: F)
+    where
+        V: Serialize + DeserializeOwned,
+        F: Fn(&[u8], &mut V) -> Result<(), OrmError> + Send + Sync + 'static,
+    {
+        let hook = move |tree: &Tree, key: &[u8], bytes: Vec<u8>| {
+            let mut value: V = tree.decode(key, &bytes)?;
+            hook(key, &mut value)?;
+            tree.encode(key, &value)
+        };
+        self.schema.update_hooks(|hooks| add(&mut hooks.before_save, name, Arc::new(hook)));
+    }
+
+    pub fn after_save<V, F>(&self, name: &str, hook: F)
+    where
+        V: DeserializeOwned,
+        F: Fn(&[u8], &V) + Send + Sync + 'static,
+    {
+        let hook = observer(move |key, value: &V| {
+            hook(key, value);
+            Ok(())
+        });
+        self.schema.update_hooks(|hooks| add(&mut hooks.after_save, name, hook));
+    }
+
+    // Recibe el valor que se va a borrar; devolver un error cancela el borrado
+    pub fn before_delete<V, F>(&self, name: &str, hook: F)
+    where
+        V: DeserializeOwned,
+        F: Fn(&[u8], &V) -> Result<(), OrmError> + Send + Sync + 'static,
+    {
+        self.schema.update_hooks(|hooks| add(&mut hooks.before_delete, name, observer(hook)));
+    }
+
+    pub fn after_delete<V, F>(&self, name: &str, hook: F)
+    where
+        V: DeserializeOwned,
+        F: Fn(&[u8], &V) + Send + Sync + 'static,
+    {
+        let hook = observer(move |key, value: &V| {
+            hook(key, value);
+            Ok(())
+        });
+        self.schema.update_hooks(|hooks| add(&mut hooks.after_delete, name, hook));
+    }
+
+    pub fn use_model_hooks<M: ModelHooks + 'static>(&self) {
+        let name = std::any::type_name::<M>();
+        self.before_save(name, |_, model: &mut M| model.before_save());
+        self.after_save(name, |_, model: &M| model.after_save());
+        self.before_delete(name, |_, model: &M| model.before_delete());
+        self.after_delete(name, |_, model: &M| model.after_delete());
+    }
+}
+
+fn observer<V, F>(hook: F) -> Arc<Observer>
+where
+    V: DeserializeOwned,
+    F: Fn(&[u8], &V) -> Result<(), OrmError> + Send + Sync + 'static,
+{
+    Arc::new(move |tree: &Tree, key: &[u8], bytes: &[u8]| hook(key, &tree.decode(key, bytes)?))
+}
@@ -0,0 +1,435 @@
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use sled::{
+    transaction::{ConflictableTransactionError, ConflictableTransactionResult, Transactional, TransactionalTree},
+    IVec,
+};
+
+use crate::{ttl, Key, OrmError, Tree};
+
+type Extractor = dyn Fn(&Tree, &[u8], &[u8]) -> Result<Option<Vec<u8>>, OrmError> + Send + Sync;
+
+// Índice secundario guardado en un árbol compañero `__idx:<tree>:<name>`.
+// Cada entrada es `len(valor) ++ valor ++ clave primaria`, así un scan_prefix
+// sobre `len ++ valor` devuelve exactamente los registros con ese valor.
+// En los índices únicos la entrada es sólo `len ++ valor` y su valor es la
+// clave primaria que lo reservó. Si el extractor devuelve None (p. ej. una
+// clave foránea nula) el registro no tiene entrada. La clave vacía marca un
+// índice construido por completo; sin ella se vuelve a construir al abrirlo.
+//
+// Los índices inversos de las relaciones guardan además, en `0xffffffff ++
+// valor`, un contador que sube cada vez que cambia el conjunto de registros
+// con ese valor. Así un borrado puede comprobar dentro de su transacción
+// (donde sled no deja recorrer árboles) que los hijos que buscó antes siguen
+// siendo los mismos.
+pub(crate) struct Index {
+    pub(crate) name: String,
+    pub(crate) tree: sled::Tree,
+    pub(crate) unique: bool,
+    // Árbol padre si es el índice inverso de una relación
+    pub(crate) parent: Option<String>,
+    extract: Box<Extractor>,
+    // false mientras se construye: las escrituras ya lo mantienen pero las
+    // consultas todavía no lo ven
+    ready: AtomicBool,
+}
+
+const BUILT: &[u8] = b"";
+const BUILD_BATCH: usize = 512;
+
+impl Index {
+    pub(crate) fn new<V, I, F>(name: &str, tree: sled::Tree, unique: bool, parent: Option<String>, f: F) -> Self
+    where
+        V: DeserializeOwned,
+        I: Key,
+        F: Fn(&V) -> Option<I> + Send + Sync + 'static,
+    {
+        Index {
+            name: name.to_string(),
+            tree,
+            unique,
+            parent,
+            extract: Box::new(move |owner, key, bytes| {
+                let value: V = owner.decode(key, bytes)?;
+                Ok(f(&value).map(|value| value.to_key_bytes()))
+            }),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn tree_name(owner: &str, index: &str) -> String {
+        format!("__idx:{}:{}", owner, index)
+    }
+
+    pub(crate) fn extract(&self, owner: &Tree, key: &[u8], bytes: &[u8]) -> Result<Option<Vec<u8>>, OrmError> {
+        (self.extract)(owner, key, bytes)
+    }
+
+    pub(crate) fn prefix(value: &[u8]) -> Vec<u8> {
+        let mut prefix = Vec::with_capacity(4 + value.len());
+        prefix.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        prefix.extend_from_slice(value);
+        prefix
+    }
+
+    pub(crate) fn entry(value: &[u8], key: &[u8]) -> Vec<u8> {
+        let mut entry = Self::prefix(value);
+        entry.extend_from_slice(key);
+        entry
+    }
+
+    pub(crate) fn generation_key(value: &[u8]) -> Vec<u8> {
+        let mut key = vec![0xff; 4];
+        key.extend_from_slice(value);
+        key
+    }
+
+    // Sube el contador de `value`; devuelve el que había
+    pub(crate) fn bump_generation(tx: &TransactionalTree, value: &[u8]) -> ConflictableTransactionResult<Option<IVec>, OrmError> {
+        let key = Self::generation_key(value);
+        let current = tx.get(&key)?;
+        let next = current.as_deref().and_then(|bytes| bytes.try_into().ok()).map_or(0, u64::from_be_bytes) + 1;
+        tx.insert(key, &next.to_be_bytes()[..])?;
+        Ok(current)
+    }
+
+    pub(crate) fn lookup(&self, value: &[u8]) -> Result<Vec<IVec>, OrmError> {
+        let prefix = Self::prefix(value);
+        if self.unique {
+            return Ok(self.tree.get(&prefix)?.into_iter().collect());
+        }
+        let mut keys = Vec::new();
+        for item in self.tree.scan_prefix(&prefix) {
+            let (entry, _) = item?;
+            keys.push(IVec::from(&entry[prefix.len()..]));
+        }
+        Ok(keys)
+    }
+
+    // (valor, clave primaria) de una entrada; None para la marca de construido
+    fn parse_entry<'e>(&self, entry: &'e [u8], stored: &'e [u8]) -> Option<(&'e [u8], &'e [u8])> {
+        let len = u32::from_be_bytes(entry.get(..4)?.try_into().ok()?) as usize;
+        let value = entry.get(4..4 + len)?;
+        Some((value, if self.unique { stored } else { &entry[4 + len..] }))
+    }
+
+    // Pone el índice al día con los registros actuales sin vaciarlo: primero
+    // quita las entradas que ya no corresponden a ningún registro y después
+    // añade las que faltan. Cada lote se comprueba dentro de una transacción
+    // con los valores vigentes, así que las escrituras concurrentes (que ya
+    // mantienen el índice) no se pierden y las consultas nunca lo ven vacío.
+    pub(crate) fn build(&self, owner: &Tree) -> Result<(), OrmError> {
+        let trees = [&owner.tree, &self.tree];
+
+        let mut start = Bound::Unbounded;
+        loop {
+            let chunk = self
+                .tree
+                .range::<Vec<u8>, _>((start, Bound::Unbounded))
+                .keys()
+                .take(BUILD_BATCH)
+                .collect::<Result<Vec<IVec>, _>>()?;
+            trees.transaction(|txs| {
+                let (primary, index) = (&txs[0], &txs[1]);
+                for entry in &chunk {
+                    let Some(stored) = index.get(entry)? else {
+                        continue;
+                    };
+                    let Some((value, key)) = self.parse_entry(entry, &stored) else {
+                        continue;
+                    };
+                    let current = match primary.get(key)? {
+                        Some(bytes) => self.extract(owner, key, &bytes).map_err(ConflictableTransactionError::Abort)?,
+                        None => None,
+                    };
+                    if current.as_deref() != Some(value) {
+                        index.remove(entry)?;
+                        if self.parent.is_some() {
+                            Self::bump_generation(index, value)?;
+                        }
+                    }
+                }
+                Ok(())
+            })?;
+            match chunk.last() {
+                Some(last) if chunk.len() == BUILD_BATCH => start = Bound::Excluded(last.to_vec()),
+                _ => break,
+            }
+        }
+
+        let mut start = Bound::Unbounded;
+        loop {
+            let chunk = owner
+                .tree
+                .range::<Vec<u8>, _>((start, Bound::Unbounded))
+                .keys()
+                .take(BUILD_BATCH)
+                .collect::<Result<Vec<IVec>, _>>()?;
+            trees.transaction(|txs| {
+                let (primary, index) = (&txs[0], &txs[1]);
+                for key in &chunk {
+                    let Some(bytes) = primary.get(key)? else {
+                        continue;
+                    };
+                    if let Some(value) = self.extract(owner, key, &bytes).map_err(ConflictableTransactionError::Abort)?
+                        && self.insert_entry(owner, index, None, &value, key)?
+                        && self.parent.is_some()
+                    {
+                        Self::bump_generation(index, &value)?;
+                    }
+                }
+                Ok(())
+            })?;
+            match chunk.last() {
+                Some(last) if chunk.len() == BUILD_BATCH => start = Bound::Excluded(last.to_vec()),
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn violation(&self, owner: &Tree, existing: &[u8]) -> OrmError {
+        OrmError::UniqueViolation {
+            tree: owner.name(),
+            field: self.name.clone(),
+            existing_key: existing.to_vec(),
+        }
+    }
+
+    fn remove_entry(&self, tx: &TransactionalTree, value: &[u8], key: &[u8]) -> ConflictableTransactionResult<(), OrmError> {
+        if !self.unique {
+            tx.remove(Self::entry(value, key))?;
+            return Ok(());
+        }
+        // Sólo se libera la reserva si pertenece a este registro
+        let prefix = Self::prefix(value);
+        if tx.get(&prefix)?.is_some_and(|owner| owner == key) {
+            tx.remove(prefix)?;
+        }
+        Ok(())
+    }
+
+    // Devuelve si la entrada no existía. Con `ttl` (el árbol clave -> caducidad
+    // del dueño) la reserva de un registro caducado sin purgar queda libre:
+    // al purgarlo sólo se quita si sigue siendo suya
+    fn insert_entry(
+        &self,
+        owner: &Tree,
+        tx: &TransactionalTree,
+        ttl: Option<&TransactionalTree>,
+        value: &[u8],
+        key: &[u8],
+    ) -> ConflictableTransactionResult<bool, OrmError> {
+        if !self.unique {
+            return Ok(tx.insert(Self::entry(value, key), IVec::default())?.is_none());
+        }
+        let prefix = Self::prefix(value);
+        match tx.get(&prefix)? {
+            Some(existing) if existing != key => {
+                match ttl {
+                    Some(ttl) if ttl::expired(ttl.get(&existing)?) => {
+                        tx.insert(prefix, key)?;
+                        Ok(true)
+                    }
+                    _ => Err(ConflictableTransactionError::Abort(self.violation(owner, &existing))),
+                }
+            }
+            existing => {
+                tx.insert(prefix, key)?;
+                Ok(existing.is_none())
+            }
+        }
+    }
+}
+
+// Escribe (o borra, si `value` es None) el registro primario y actualiza sus
+// índices dentro de la misma transacción. `txs[0]` es el árbol primario y
+// `txs[i + 1]` el árbol de `indexes[i]`; `ttl`, si el árbol tiene
+// caducidades, es el que guarda la de cada clave. Por cada entrada que cambia
+// se llama a `changed(i, valor, añadida)`.
+pub(crate) fn apply<C>(
+    owner: &Tree,
+    indexes: &[Arc<Index>],
+    txs: &[TransactionalTree],
+    ttl: Option<&TransactionalTree>,
+    key: &[u8],
+    value: Option<&[u8]>,
+    mut changed: C,
+) -> ConflictableTransactionResult<Option<IVec>, OrmError>
+where
+    C: FnMut(usize, &[u8], bool) -> ConflictableTransactionResult<(), OrmError>,
+{
+    let primary = &txs[0];
+    let old = match value {
+        Some(bytes) => primary.insert(key, bytes)?,
+        None => primary.remove(key)?,
+    };
+
+    let extract = |index: &Index, bytes: Option<&[u8]>| match bytes {
+        Some(bytes) => index.extract(owner, key, bytes).map_err(ConflictableTransactionError::Abort),
+        None => Ok(None),
+    };
+    let mut changes = Vec::new();
+    for (i, index) in indexes.iter().enumerate() {
+        let old_value = extract(index, old.as_deref())?;
+        let new_value = extract(index, value)?;
+        if old_value != new_value {
+            changes.push((i, old_value, new_value));
+        }
+    }
+
+    // Primero se liberan las entradas viejas para que otro registro del mismo
+    // lote pueda quedarse con un valor único que éste deja
+    for (i, old_value, _) in &changes {
+        if let Some(old_value) = old_value {
+            indexes[*i].remove_entry(&txs[i + 1], old_value, key)?;
+            changed(*i, old_value, false)?;
+        }
+    }
+    for (i, _, new_value) in &changes {
+        if let Some(new_value) = new_value {
+            indexes[*i].insert_entry(owner, &txs[i + 1], ttl, new_value, key)?;
+            changed(*i, new_value, true)?;
+        }
+    }
+
+    Ok(old)
+}
+
+impl Tree {
+    // Registra un índice secundario y, si no estaba construido en disco, lo
+    // genera a partir de los registros existentes. Hasta que termina, las
+    // consultas por el índice fallan con UnknownIndex; si la construcción
+    // falla (p. ej. valores únicos repetidos) el índice no queda registrado.
+    pub fn create_index<V, I, F>(&self, name: &str, f: F) -> Result<(), OrmError>
+    where
+        V: DeserializeOwned,
+        I: Key,
+        F: Fn(&V) -> I + Send + Sync + 'static,
+    {
+        self.register_index(name, false, None, move |value: &V| Some(f(value)))
+    }
+
+    // Igual que create_index, pero un valor sólo puede pertenecer a un registro;
+    // insert y update fallan con OrmError::UniqueViolation si ya está reservado
+    pub fn create_unique_index<V, I, F>(&self, name: &str, f: F) -> Result<(), OrmError>
+    where
+        V: DeserializeOwned,
+        I: Key,
+        F: Fn(&V) -> I + Send + Sync + 'static,
+    {
+        self.register_index(name, true, None, move |value: &V| Some(f(value)))
+    }
+
+    pub(crate) fn register_index<V, I, F>(&self, name: &str, unique: bool, parent: Option<String>, f: F) -> Result<(), OrmError>
+    where
+        V: DeserializeOwned,
+        I: Key,
+        F: Fn(&V) -> Option<I> + Send + Sync + 'static,
+    {
+        if self.schema.index(name).is_some() {
+            return Ok(());
+        }
+        let _building = self.schema.index_builds.lock().unwrap();
+        if self.schema.index(name).is_some() {
+            return Ok(());
+        }
+
+        let index_tree = self.conn.db.open_tree(Index::tree_name(&self.name(), name))?;
+        let index = Arc::new(Index::new(name, index_tree, unique, parent, f));
+        if index.tree.contains_key(BUILT)? {
+            index.ready.store(true, Ordering::Release);
+            let _indexes = self.conn.registry.changing_indexes();
+            self.schema.add_index(index);
+            return Ok(());
+        }
+
+        // Restos de una construcción interrumpida; nadie escribe todavía en él
+        index.tree.clear()?;
+        {
+            let _indexes = self.conn.registry.changing_indexes();
+            self.schema.add_index(index.clone());
+        }
+        match index.build(self).and_then(|_| Ok(index.tree.insert(BUILT, IVec::default())?)) {
+            Ok(_) => {
+                index.ready.store(true, Ordering::Release);
+                Ok(())
+            }
+            Err(e) => {
+                {
+                    let _indexes = self.conn.registry.changing_indexes();
+                    self.schema.remove_index(name);
+                }
+                index.tree.clear()?;
+                Err(e)
+            }
+        }
+    }
+
+    // Las migraciones reescriben los registros sin pasar por los índices:
+    // antes de empezar se quitan las marcas de construido de todos los índices
+    // guardados del árbol (también los que esta conexión no ha registrado) y
+    // al terminar se reconstruyen los registrados. Si el proceso se corta
+    // entre medias, cada índice se reconstruye la próxima vez que se registre.
+    pub(crate) fn invalidate_indexes(&self) -> Result<(), OrmError> {
+        let prefix = Index::tree_name(&self.name(), "");
+        for name in self.conn.db.tree_names() {
+            if name.starts_with(prefix.as_bytes()) {
+                self.conn.db.open_tree(name)?.remove(BUILT)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn rebuild_indexes(&self) -> Result<(), OrmError> {
+        for index in self.schema.indexes() {
+            let _building = self.schema.index_builds.lock().unwrap();
+            // Los que no llegaron a construirse ya no están registrados
+            if index.is_ready() {
+                index.build(self)?;
+                index.tree.insert(BUILT, IVec::default())?;
+            }
+        }
+        Ok(())
+    }
+
+    // Quita las entradas sobrantes y añade las que falten; el índice sigue
+    // disponible mientras tanto
+    pub fn rebuild_index(&self, name: &str) -> Result<(), OrmError> {
+        let index = self.index(name)?;
+        let _building = self.schema.index_builds.lock().unwrap();
+        index.build(self)
+    }
+
+    pub fn find_keys_by_index<I: Key>(&self, name: &str, value: &I) -> Result<Vec<IVec>, OrmError> {
+        self.index(name)?.lookup(&value.to_key_bytes())
+    }
+
+    pub fn find_by_index<I, V>(&self, name: &str, value: &I) -> Result<Vec<V>, OrmError>
+    where
+        I: Key,
+        V: DeserializeOwned,
+    {
+        let mut results = Vec::new();
+        for key in self.find_keys_by_index(name, value)? {
+            if let Some(value) = self.get(&key)? {
+                results.push(value);
+            }
+        }
+        Ok(results)
+    }
+
+    pub(crate) fn index(&self, name: &str) -> Result<Arc<Index>, OrmError> {
+        self.schema.index(name).ok_or_else(|| OrmError::UnknownIndex {
+            tree: self.name(),
+            index: name.to_string(),
+        })
+    }
+}
@@ -0,0 +1,151 @@
+use std::iter::Rev;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use serde::de::DeserializeOwned;
+use sled::IVec;
+
+use crate::{Key, OrmError, Tree};
+
+// Iterador perezoso sobre sled::Iter: decodifica un registro cada vez,
+// así se puede cortar antes o recorrer árboles enormes con memoria constante
+pub struct TypedIter<'a, K, V> {
+    tree: &'a Tree,
+    inner: sled::Iter,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<'a, K, V> TypedIter<'a, K, V>
+where
+    K: Key,
+    V: DeserializeOwned,
+{
+    pub(crate) fn new(tree: &'a Tree, inner: sled::Iter) -> Self {
+        TypedIter { tree, inner, marker: PhantomData }
+    }
+
+    // None si el registro caducó y hay que saltarlo
+    fn decode(&self, item: sled::Result<(IVec, IVec)>) -> Option<Result<(K, V), OrmError>> {
+        let decode = || {
+            let (key, bytes) = item?;
+            if self.tree.is_expired(&key)? {
+                return Ok(None);
+            }
+            let value = self.tree.decode(&key, &bytes)?;
+            Ok(Some((self.tree.decode_key(&key)?, value)))
+        };
+        decode().transpose()
+    }
+}
+
+impl<K, V> Iterator for TypedIter<'_, K, V>
+where
+    K: Key,
+    V: DeserializeOwned,
+{
+    type Item = Result<(K, V), OrmError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let item = self.inner.next()?;
+            if let Some(item) = self.decode(item) {
+                return Some(item);
+            }
+        }
+    }
+}
+
+impl<K, V> DoubleEndedIterator for TypedIter<'_, K, V>
+where
+    K: Key,
+    V: DeserializeOwned,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            let item = self.inner.next_back()?;
+            if let Some(item) = self.decode(item) {
+                return Some(item);
+            }
+        }
+    }
+}
+
+// Igual que TypedIter pero sólo entrega los registros que cumplen el predicado.
+// Los errores se entregan siempre, ya que no se puede evaluar el predicado sobre ellos.
+pub struct FilterIter<'a, K, V, F> {
+    inner: TypedIter<'a, K, V>,
+    predicate: F,
+}
+
+impl<K, V, F> Iterator for FilterIter<'_, K, V, F>
+where
+    K: Key,
+    V: DeserializeOwned,
+    F: Fn(&V) -> bool,
+{
+    type Item = Result<(K, V), OrmError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let predicate = &self.predicate;
+        self.inner.find(|item| item.as_ref().map_or(true, |(_, value)| predicate(value)))
+    }
+}
+
+impl Tree {
+    pub fn iter<V: DeserializeOwned>(&self) -> TypedIter<'_, IVec, V> {
+        TypedIter::new(self, self.tree.iter())
+    }
+
+    pub fn filter_iter<V, F>(&self, predicate: F) -> FilterIter<'_, IVec, V, F>
+    where
+        V: DeserializeOwned,
+        F: Fn(&V) -> bool,
+    {
+        FilterIter { inner: self.iter(), predicate }
+    }
+
+    // Las claves se comparan ya codificadas, por eso K tiene que ordenar igual
+    // en bytes que en memoria (ver Key)
+    pub fn range<K, V, R>(&self, range: R) -> TypedIter<'_, K, V>
+    where
+        K: Key,
+        V: DeserializeOwned,
+        R: RangeBounds<K>,
+    {
+        TypedIter::new(self, self.tree.range(key_bounds(&range)))
+    }
+
+    pub fn range_rev<K, V, R>(&self, range: R) -> Rev<TypedIter<'_, K, V>>
+    where
+        K: Key,
+        V: DeserializeOwned,
+        R: RangeBounds<K>,
+    {
+        self.range(range).rev()
+    }
+
+    pub fn prefix<V, P>(&self, prefix: P) -> TypedIter<'_, IVec, V>
+    where
+        V: DeserializeOwned,
+        P: AsRef<[u8]>,
+    {
+        TypedIter::new(self, self.tree.scan_prefix(prefix))
+    }
+
+    pub fn prefix_rev<V, P>(&self, prefix: P) -> Rev<TypedIter<'_, IVec, V>>
+    where
+        V: DeserializeOwned,
+        P: AsRef<[u8]>,
+    {
+        self.prefix(prefix).rev()
+    }
+}
+
+pub(crate) fn key_bounds<K: Key, R: RangeBounds<K>>(range: &R) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let convert = |bound: Bound<&K>| match bound {
+        Bound::Included(key) => Bound::Included(key.to_key_bytes()),
+        Bound::Excluded(key) => Bound::Excluded(key.to_key_bytes()),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (convert(range.start_bound()), convert(range.end_bound()))
+}
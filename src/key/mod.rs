@@ -0,0 +1,109 @@
+use sled::IVec;
+
+// Conversión entre claves tipadas y los bytes que guarda sled.
+// Los enteros se codifican en big endian para que el orden de sled
+// coincida con el orden numérico.
+pub trait Key: Sized {
+    fn to_key_bytes(&self) -> Vec<u8>;
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl Key for String {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl Key for Vec<u8> {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl Key for IVec {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(IVec::from(bytes))
+    }
+}
+
+impl<const N: usize> Key for [u8; N] {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok()
+    }
+}
+
+macro_rules! unsigned_key {
+    ($($t:ty),*) => {
+        $(
+            impl Key for $t {
+                fn to_key_bytes(&self) -> Vec<u8> {
+                    self.to_be_bytes().to_vec()
+                }
+
+                fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+                    Some(<$t>::from_be_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+// Se invierte el bit de signo para que los negativos ordenen antes que los positivos
+macro_rules! signed_key {
+    ($($t:ty => $u:ty),*) => {
+        $(
+            impl Key for $t {
+                fn to_key_bytes(&self) -> Vec<u8> {
+                    ((*self as $u) ^ (1 << (<$u>::BITS - 1))).to_be_bytes().to_vec()
+                }
+
+                fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+                    let raw = <$u>::from_be_bytes(bytes.try_into().ok()?);
+                    Some((raw ^ (1 << (<$u>::BITS - 1))) as $t)
+                }
+            }
+        )*
+    };
+}
+
+unsigned_key!(u8, u16, u32, u64, u128);
+signed_key!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+#[cfg(feature = "uuid")]
+impl Key for uuid::Uuid {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+        uuid::Uuid::from_slice(bytes).ok()
+    }
+}
+
+#[cfg(feature = "ulid")]
+impl Key for ulid::Ulid {
+    fn to_key_bytes(&self) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+
+    fn from_key_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(ulid::Ulid::from_bytes(bytes.try_into().ok()?))
+    }
+}
@@ -0,0 +1,156 @@
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::{Key, OrmError, Tree, ORM};
+
+// 2015-01-01T00:00:00Z, el epoch de los snowflakes de Discord
+const SNOWFLAKE_EPOCH_MS: u64 = 1_420_070_400_000;
+
+// Cómo genera claves Tree::insert_auto. Todas se guardan en big endian, así
+// que Sequence, Snowflake, UuidV7 y Ulid ordenan por momento de creación.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum KeyStrategy {
+    // Contador monotónico de sled (Db::generate_id), compartido por toda la base; u64
+    #[default]
+    Sequence,
+    // u64 estilo Discord: ms desde 2015 (42 bits), worker (10 bits, hasta
+    // 1023) y secuencia (12 bits)
+    Snowflake { worker: u16 },
+    // uuid::Uuid aleatorio
+    #[cfg(feature = "uuid")]
+    UuidV4,
+    // uuid::Uuid con la marca de tiempo delante
+    #[cfg(feature = "uuid")]
+    UuidV7,
+    // ulid::Ulid monotónico dentro del mismo milisegundo
+    #[cfg(feature = "ulid")]
+    Ulid,
+}
+
+const MAX_WORKER: u16 = 0x3ff;
+
+impl KeyStrategy {
+    // Un worker más grande se truncaría y repetiría los ids de otro
+    fn check(self) -> Result<Self, OrmError> {
+        match self {
+            KeyStrategy::Snowflake { worker } if worker > MAX_WORKER => Err(OrmError::InvalidWorker(worker)),
+            strategy => Ok(strategy),
+        }
+    }
+}
+
+// Estado del generador de un árbol; vive en su TreeSchema
+pub(crate) struct KeyGenerator {
+    pub(crate) strategy: KeyStrategy,
+    // (último ms, secuencia) del último snowflake
+    snowflake: Mutex<(u64, u16)>,
+    #[cfg(feature = "ulid")]
+    ulid: Mutex<ulid::Generator>,
+}
+
+impl KeyGenerator {
+    pub(crate) fn new(strategy: KeyStrategy) -> Self {
+        KeyGenerator {
+            strategy,
+            snowflake: Mutex::new((0, 0)),
+            #[cfg(feature = "ulid")]
+            ulid: Mutex::new(ulid::Generator::new()),
+        }
+    }
+
+    fn generate(&self, db: &sled::Db) -> Result<Vec<u8>, OrmError> {
+        Ok(match self.strategy {
+            KeyStrategy::Sequence => db.generate_id()?.to_be_bytes().to_vec(),
+            KeyStrategy::Snowflake { worker } => self.snowflake(worker).to_be_bytes().to_vec(),
+            #[cfg(feature = "uuid")]
+            KeyStrategy::UuidV4 => uuid::Uuid::new_v4().as_bytes().to_vec(),
+            #[cfg(feature = "uuid")]
+            KeyStrategy::UuidV7 => uuid::Uuid::now_v7().as_bytes().to_vec(),
+            #[cfg(feature = "ulid")]
+            KeyStrategy::Ulid => self.ulid().to_bytes().to_vec(),
+        })
+    }
+
+    // Si se agota la secuencia del milisegundo o el reloj retrocede se sigue
+    // por el milisegundo siguiente al último usado, así nunca se repiten
+    fn snowflake(&self, worker: u16) -> u64 {
+        let mut state = self.snowflake.lock().unwrap();
+        let (last, sequence) = *state;
+        let now = now_ms().saturating_sub(SNOWFLAKE_EPOCH_MS);
+        let next = match now.cmp(&last) {
+            std::cmp::Ordering::Greater => (now, 0),
+            _ if sequence < 0xfff => (last, sequence + 1),
+            _ => (last + 1, 0),
+        };
+        *state = next;
+        (next.0 << 22) | ((worker as u64) << 12) | next.1 as u64
+    }
+
+    // generate sólo falla si se agota la parte aleatoria en un milisegundo
+    #[cfg(feature = "ulid")]
+    fn ulid(&self) -> ulid::Ulid {
+        let mut generator = self.ulid.lock().unwrap();
+        loop {
+            match generator.generate() {
+                Ok(ulid) => return ulid,
+                Err(_) => std::thread::yield_now(),
+            }
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+impl ORM {
+    // Abre el árbol fijando cómo genera claves insert_auto, igual que tree_with_codec
+    pub fn tree_with_key_strategy(&self, name: &str, strategy: KeyStrategy) -> Result<Tree, OrmError> {
+        self.conn.registry.schema(name).set_key_strategy(strategy.check()?);
+        self.tree(name)
+    }
+}
+
+impl Tree {
+    // Falla con OrmError::InvalidWorker si un worker de snowflake pasa de 1023
+    pub fn set_key_strategy(&self, strategy: KeyStrategy) -> Result<(), OrmError> {
+        self.schema.set_key_strategy(strategy.check()?);
+        Ok(())
+    }
+
+    pub fn key_strategy(&self) -> KeyStrategy {
+        self.schema.key_generator().strategy
+    }
+
+    // K tiene que corresponder con la estrategia: u64 para Sequence y
+    // Snowflake, uuid::Uuid o [u8; 16] para los UUID y ulid::Ulid o [u8; 16]
+    // para Ulid. Si no, falla con OrmError::InvalidKey.
+    pub fn generate_key<K: Key>(&self) -> Result<K, OrmError> {
+        let bytes = self.schema.key_generator().generate(&self.conn.db)?;
+        self.decode_key(&bytes)
+    }
+
+    // Inserta `value` con una clave nueva y la devuelve. Si la clave ya
+    // existe (otro proceso con el mismo worker, un reloj atrasado...) no se
+    // sobrescribe nada y falla con OrmError::Conflict.
+    pub fn insert_auto<K, V>(&self, value: &V) -> Result<K, OrmError>
+    where
+        K: Key,
+        V: Serialize,
+    {
+        let key: K = self.generate_key()?;
+        self.insert_new(&key.to_key_bytes(), value)?;
+        Ok(key)
+    }
+
+    pub(crate) fn insert_new<V: Serialize>(&self, key: &[u8], value: &V) -> Result<(), OrmError> {
+        let bytes = self.encode(key, value)?;
+        self.atomic_update_raw(key, |current| match current {
+            Some(_) => Err(OrmError::Conflict { tree: self.name(), key: key.to_vec(), current: None }),
+            None => Ok((Some(bytes.clone()), ())),
+        })
+    }
+}
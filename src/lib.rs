@@ -1,13 +1,63 @@
+use std::sync::Arc;
+
 use bincode::config::{BigEndian, Configuration, Fixint};
 
+mod batch;
+mod cas;
+mod codec;
+mod collection;
+mod compression;
 mod connection;
+mod encryption;
+mod error;
+mod hooks;
+mod index;
+mod iter;
+mod key;
+mod keygen;
+mod migration;
+mod model;
 mod orm;
+mod page;
+mod query;
+mod relation;
+mod schema;
+mod subscription;
+mod transaction;
 mod trees;
-mod macros;
+mod ttl;
+
+pub use batch::{BatchItemError, WriteBatch};
+pub use cas::Versioned;
+pub use codec::{ByteCodec, CodecError, CodecKind};
+pub(crate) use codec::Codec;
+pub use collection::Collection;
+pub use compression::Compression;
+pub use encryption::{Encryption, Keyring};
+pub use error::OrmError;
+pub use hooks::ModelHooks;
+pub use iter::{FilterIter, TypedIter};
+pub use key::Key;
+pub use keygen::KeyStrategy;
+pub use migration::{MigrationProgress, Migrations};
+pub use model::Model;
+pub use page::{Cursor, Page};
+pub use query::Query;
+pub use relation::{BelongsTo, BlockingReference, OnDelete};
+pub use subscription::{Change, Subscription};
+#[cfg(feature = "async")]
+pub use subscription::ChangeStream;
+pub use ttl::Sweeper;
+pub use transaction::{TransactionTrees, TxResult, TypedTx};
+pub use sled_orm_derive::Model;
 
 
+#[derive(Clone)]
 pub struct Connection {
-    pub db: sled::Db
+    pub db: sled::Db,
+    registry: Arc<schema::Registry>,
+    codec: CodecKind,
+    keyring: Arc<Keyring>,
 }
 
 pub struct ORM {
@@ -17,9 +67,11 @@ pub struct ORM {
 
 
 
+#[derive(Clone)]
 pub struct Tree {
     pub conn: Connection,
-    pub tree: sled::Tree
+    pub tree: sled::Tree,
+    schema: Arc<schema::TreeSchema>,
 }
 
 pub fn bincode_get_config() -> Configuration<BigEndian, Fixint>{
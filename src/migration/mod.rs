@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::{de::DeserializeOwned, Serialize};
+use sled::IVec;
+
+use crate::{schema::TreeSchema, CodecKind, Connection, OrmError, Tree, ORM};
+
+// Árbol donde se guarda la versión de esquema de cada árbol y, mientras una
+// migración está a medias, la última clave ya convertida
+pub(crate) const META_TREE: &str = "__meta";
+const BATCH_SIZE: usize = 512;
+
+type Step = dyn Fn(&Tree, &[u8], &[u8]) -> Result<Vec<u8>, OrmError> + Send + Sync;
+type Progress = dyn Fn(&MigrationProgress) + Send + Sync;
+
+// Estado de una migración en curso, se pasa al callback de on_progress
+#[derive(Debug, Clone)]
+pub struct MigrationProgress {
+    pub tree: String,
+    pub from_version: u64,
+    pub to_version: u64,
+    pub migrated: usize,
+    pub total: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct TreeMigrations {
+    steps: RwLock<BTreeMap<u64, Arc<Step>>>,
+    progress: RwLock<Option<Arc<Progress>>>,
+    // Evita que dos hilos que abren el mismo árbol migren los registros dos veces
+    running: Mutex<()>,
+    // Última versión a la que se llegó, para no leer __meta en cada apertura
+    reached: AtomicU64,
+}
+
+// Registra los pasos de migración de un árbol. Cada paso convierte los
+// registros de la versión `from` a la `from + 1`; la versión objetivo es la
+// siguiente al último paso registrado.
+pub struct Migrations {
+    schema: Arc<TreeSchema>,
+}
+
+impl Migrations {
+    // `migrate` recibe los bytes que produjo el codec con la versión anterior
+    // (ya descifrados y descomprimidos) y devuelve el valor nuevo
+    pub fn step<N, F>(self, from: u64, migrate: F) -> Self
+    where
+        N: Serialize,
+        F: Fn(&[u8]) -> Result<N, OrmError> + Send + Sync + 'static,
+    {
+        let step = move |tree: &Tree, key: &[u8], old: &[u8]| tree.encode(key, &migrate(&tree.unseal::<[u8]>(key, old)?)?);
+        self.schema.migrations.steps.write().unwrap().insert(from, Arc::new(step));
+        self
+    }
+
+    // Igual que step, pero decodifica el registro viejo como `O`
+    pub fn map<O, N, F>(self, from: u64, migrate: F) -> Self
+    where
+        O: DeserializeOwned,
+        N: Serialize,
+        F: Fn(O) -> N + Send + Sync + 'static,
+    {
+        let step = move |tree: &Tree, key: &[u8], old: &[u8]| tree.encode(key, &migrate(tree.decode(key, old)?));
+        self.schema.migrations.steps.write().unwrap().insert(from, Arc::new(step));
+        self
+    }
+
+    pub fn on_progress<F>(self, progress: F) -> Self
+    where
+        F: Fn(&MigrationProgress) + Send + Sync + 'static,
+    {
+        *self.schema.migrations.progress.write().unwrap() = Some(Arc::new(progress));
+        self
+    }
+}
+
+impl Connection {
+    // Las migraciones registradas se ejecutan la próxima vez que se abre el árbol
+    pub fn migrations(&self, tree: &str) -> Migrations {
+        Migrations { schema: self.registry.schema(tree) }
+    }
+}
+
+fn version_key(tree: &str) -> String {
+    format!("version:{}", tree)
+}
+
+fn cursor_key(tree: &str) -> String {
+    format!("cursor:{}", tree)
+}
+
+impl ORM {
+    // Los metadatos siempre van en bincode, sea cual sea el codec de la conexión
+    pub(crate) fn meta(&self) -> Result<Tree, OrmError> {
+        self.tree_with_codec(META_TREE, CodecKind::Bincode)
+    }
+
+    pub fn schema_version(&self, tree: &str) -> Result<u64, OrmError> {
+        Ok(self.meta()?.get(version_key(tree))?.unwrap_or(0))
+    }
+
+    // Aplica los pasos pendientes por lotes. Cada lote se escribe junto con la
+    // última clave convertida en una sola transacción, así que si el proceso
+    // se corta la migración continúa donde se quedó. Los registros se
+    // reescriben tal cual, sin hooks ni índices (que no sabrían leer el
+    // formato viejo) y conservando su caducidad; los índices se reconstruyen
+    // al terminar.
+    pub(crate) fn migrate(&self, tree: &Tree) -> Result<(), OrmError> {
+        let migrations = &tree.schema.migrations;
+        let Some(target) = migrations.steps.read().unwrap().keys().next_back().map(|v| v + 1) else {
+            return Ok(());
+        };
+        if migrations.reached.load(Ordering::Acquire) >= target {
+            return Ok(());
+        }
+        let _running = migrations.running.lock().unwrap();
+
+        let name = tree.name();
+        let meta = self.meta()?;
+        let progress = migrations.progress.read().unwrap().clone();
+
+        let mut changed = false;
+        loop {
+            let version: u64 = meta.get(version_key(&name))?.unwrap_or(0);
+            if version >= target {
+                if changed {
+                    tree.rebuild_indexes()?;
+                }
+                migrations.reached.store(version, Ordering::Release);
+                return Ok(());
+            }
+            let step = migrations
+                .steps
+                .read()
+                .unwrap()
+                .get(&version)
+                .cloned()
+                .ok_or_else(|| OrmError::MissingMigration { tree: name.clone(), version })?;
+            if !changed {
+                tree.invalidate_indexes()?;
+                changed = true;
+            }
+
+            let mut cursor: Option<Vec<u8>> = meta.get(cursor_key(&name))?;
+            let total = tree.tree.len();
+            let mut migrated = match &cursor {
+                Some(last) => tree.tree.range(..=last.as_slice()).count(),
+                None => 0,
+            };
+
+            loop {
+                let start = cursor.clone().map_or(Bound::Unbounded, Bound::Excluded);
+                let chunk = tree
+                    .tree
+                    .range((start, Bound::Unbounded))
+                    .take(BATCH_SIZE)
+                    .collect::<Result<Vec<(IVec, IVec)>, _>>()?;
+                let done = chunk.len() < BATCH_SIZE;
+                let converted = chunk
+                    .iter()
+                    .map(|(key, old)| step(tree, key, old))
+                    .collect::<Result<Vec<_>, OrmError>>()?;
+                let last = chunk.last().map(|(key, _)| key.to_vec());
+
+                self.transaction((tree, &meta), |(tx, meta_tx)| {
+                    // Si otro escritor cambió el registro después de leerlo se deja el suyo
+                    for ((key, old), value) in chunk.iter().zip(&converted) {
+                        tx.migrate_raw(key, old, value)?;
+                    }
+                    if done {
+                        meta_tx.insert(version_key(&name), &(version + 1))?;
+                        meta_tx.remove(cursor_key(&name))?;
+                    } else if let Some(last) = &last {
+                        meta_tx.insert(cursor_key(&name), last)?;
+                    }
+                    Ok(())
+                })?;
+
+                migrated += converted.len();
+                if let Some(progress) = &progress {
+                    progress(&MigrationProgress {
+                        tree: name.clone(),
+                        from_version: version,
+                        to_version: version + 1,
+                        migrated,
+                        total,
+                    });
+                }
+                if done {
+                    break;
+                }
+                cursor = last;
+            }
+        }
+    }
+}
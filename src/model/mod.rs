@@ -0,0 +1,84 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Key, OrmError, Tree, ORM};
+
+// Normalmente se implementa con #[derive(Model)]:
+//
+// #[derive(Serialize, Deserialize, Model)]
+// #[model(tree = "users")]
+// struct User {
+//     #[primary_key]
+//     id: String,
+//     #[unique]
+//     email: String,
+//     #[index]
+//     guild_id: u64,
+// }
+pub trait Model: Serialize + DeserializeOwned {
+    type PrimaryKey: Key;
+
+    const TREE: &'static str;
+
+    fn key(&self) -> &Self::PrimaryKey;
+
+    // Lo usa insert_auto para guardar la clave generada en el modelo; el
+    // derive lo genera asignando el campo #[primary_key]
+    fn set_key(&mut self, _key: Self::PrimaryKey) {}
+
+    // Declara los índices del modelo sobre su árbol; el derive lo genera
+    // a partir de los atributos #[index] y #[unique]
+    fn configure(_tree: &Tree) -> Result<(), OrmError> {
+        Ok(())
+    }
+
+    // configure sólo se ejecuta la primera vez que se abre el árbol con este
+    // modelo; si falla se vuelve a intentar en la siguiente llamada
+    fn tree(orm: &ORM) -> Result<Tree, OrmError> {
+        let tree = orm.tree(Self::TREE)?;
+        let model = std::any::type_name::<Self>();
+        if !tree.schema.is_configured(model) {
+            Self::configure(&tree)?;
+            tree.schema.mark_configured(model);
+        }
+        Ok(tree)
+    }
+
+    // Con un campo #[version] sólo se guarda si nadie lo hizo desde que se
+    // leyó (si no, OrmError::Conflict) y lo guardado lleva la versión
+    // siguiente; para volver a guardar hay que llamar antes a reload
+    fn save(&self, orm: &ORM) -> Result<(), OrmError> {
+        Self::tree(orm)?.insert(self.key().to_key_bytes(), self)
+    }
+
+    // Genera una clave con la estrategia del árbol, la escribe en el modelo y
+    // lo guarda; devuelve la clave generada. Igual que Tree::insert_auto, falla
+    // con OrmError::Conflict si la clave ya estaba ocupada.
+    fn insert_auto(&mut self, orm: &ORM) -> Result<Self::PrimaryKey, OrmError> {
+        let tree = Self::tree(orm)?;
+        let key: Self::PrimaryKey = tree.generate_key()?;
+        let bytes = key.to_key_bytes();
+        self.set_key(key);
+        tree.insert_new(&bytes, self)?;
+        tree.decode_key(&bytes)
+    }
+
+    fn load(orm: &ORM, key: &Self::PrimaryKey) -> Result<Option<Self>, OrmError> {
+        Self::tree(orm)?.get(key.to_key_bytes())
+    }
+
+    fn delete(&self, orm: &ORM) -> Result<(), OrmError> {
+        Self::tree(orm)?.delete(self.key().to_key_bytes())
+    }
+
+    // Vuelve a leer el registro guardado; falla con NotFound si ya no existe
+    fn reload(&mut self, orm: &ORM) -> Result<(), OrmError> {
+        let key = self.key().to_key_bytes();
+        match Self::tree(orm)?.get(&key)? {
+            Some(stored) => {
+                *self = stored;
+                Ok(())
+            }
+            None => Err(OrmError::NotFound { tree: Self::TREE.to_string(), key }),
+        }
+    }
+}
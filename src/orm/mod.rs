@@ -1,12 +1,32 @@
-use crate::{Tree, ORM,Connection};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Collection, Key, Tree, ORM, OrmError};
 
 
 impl ORM {
-    pub fn tree(&self, name: &str) -> Result<Tree, sled::Error> {
+    pub fn tree(&self, name: &str) -> Result<Tree, OrmError> {
         let tree = self.conn.db.open_tree(name.as_bytes())?;
-        Ok(Tree { 
-            conn: Connection { db: self.conn.db.clone() }, 
-            tree 
-        })
+        let tree = Tree { 
+            conn: self.conn.clone(), 
+            tree,
+            schema: self.conn.registry.schema(name),
+        };
+        // Lo guardado en disco (caducidades, referencias) sólo se carga la primera vez que se abre el
+        // árbol; después el esquema ya lo tiene
+        if !tree.schema.is_loaded() {
+            tree.load_ttl()?;
+            tree.load_references()?;
+            tree.schema.mark_loaded();
+        }
+        self.migrate(&tree)?;
+        Ok(tree)
+    }
+
+    pub fn collection<K, V>(&self, name: &str) -> Result<Collection<K, V>, OrmError>
+    where
+        K: Key,
+        V: Serialize + DeserializeOwned,
+    {
+        Ok(Collection::new(self.tree(name)?))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,159 @@
+use std::ops::Bound;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sled::IVec;
+
+use crate::{bincode_get_config, Key, OrmError, Tree};
+
+const PREALLOCATE_LIMIT: usize = 1024;
+
+// Posición opaca entre páginas. Guarda la última (o primera) clave vista en
+// lugar de un offset, así sigue siendo válida aunque se inserten o borren
+// registros entre una petición y la siguiente.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    key: Vec<u8>,
+    forward: bool,
+    inclusive: bool,
+}
+
+impl Cursor {
+    fn after(key: &[u8]) -> Self {
+        Cursor { key: key.to_vec(), forward: true, inclusive: false }
+    }
+
+    fn before(key: &[u8]) -> Self {
+        Cursor { key: key.to_vec(), forward: false, inclusive: false }
+    }
+
+    pub fn is_forward(&self) -> bool {
+        self.forward
+    }
+
+    // Representación en texto para guardarla, p. ej., en el custom_id de un botón
+    pub fn to_token(&self) -> String {
+        let bytes = bincode::serde::encode_to_vec(self, bincode_get_config())
+            .expect("cursor encoding cannot fail");
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn from_token(token: &str) -> Result<Self, OrmError> {
+        let invalid = || OrmError::InvalidCursor(token.to_string());
+        if !token.len().is_multiple_of(2) || !token.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&token[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        bincode::serde::decode_from_slice(&bytes, bincode_get_config())
+            .map(|(cursor, _)| cursor)
+            .map_err(|_| invalid())
+    }
+
+    fn bound(&self) -> Bound<Vec<u8>> {
+        if self.inclusive {
+            Bound::Included(self.key.clone())
+        } else {
+            Bound::Excluded(self.key.clone())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Page<K, V> {
+    pub items: Vec<(K, V)>,
+    pub next: Option<Cursor>,
+    pub prev: Option<Cursor>,
+}
+
+impl Tree {
+    pub fn page<V: DeserializeOwned>(&self, cursor: Option<Cursor>, page_size: usize) -> Result<Page<IVec, V>, OrmError> {
+        self.page_as(cursor, page_size)
+    }
+
+    pub(crate) fn page_as<K, V>(&self, cursor: Option<Cursor>, page_size: usize) -> Result<Page<K, V>, OrmError>
+    where
+        K: Key,
+        V: DeserializeOwned,
+    {
+        if page_size == 0 {
+            return Err(OrmError::InvalidPageSize);
+        }
+        let cursor = cursor.unwrap_or(Cursor { key: Vec::new(), forward: true, inclusive: true });
+
+        // Se lee un elemento de más para saber si hay otra página en esa dirección
+        let mut raw = if cursor.forward {
+            self.read_page((cursor.bound(), Bound::Unbounded), page_size.saturating_add(1), false)?
+        } else {
+            self.read_page((Bound::Unbounded, cursor.bound()), page_size.saturating_add(1), true)?
+        };
+        let has_more = raw.len() > page_size;
+        raw.truncate(page_size);
+        if !cursor.forward {
+            raw.reverse();
+        }
+
+        let (next, prev) = match (raw.first(), raw.last()) {
+            (Some((first, _)), Some((last, _))) => {
+                let more_after = if cursor.forward { has_more } else { self.has_after(last)? };
+                let more_before = if cursor.forward { self.has_before(first)? } else { has_more };
+                (
+                    more_after.then(|| Cursor::after(last)),
+                    more_before.then(|| Cursor::before(first)),
+                )
+            }
+            // Página vacía (p. ej. se borró todo lo que seguía): se ofrece volver
+            // hacia el lado contrario, incluyendo la clave del cursor
+            _ => {
+                let back = Cursor { key: cursor.key.clone(), forward: !cursor.forward, inclusive: true };
+                let range = if back.forward {
+                    (back.bound(), Bound::Unbounded)
+                } else {
+                    (Bound::Unbounded, back.bound())
+                };
+                let back = (!self.read_page(range, 1, false)?.is_empty()).then_some(back);
+                if cursor.forward { (None, back) } else { (back, None) }
+            }
+        };
+
+        let items = raw
+            .into_iter()
+            .map(|(key, bytes)| Ok((self.decode_key(&key)?, self.decode(&key, &bytes)?)))
+            .collect::<Result<_, OrmError>>()?;
+
+        Ok(Page { items, next, prev })
+    }
+
+    fn read_page(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>), n: usize, reverse: bool) -> Result<Vec<(IVec, IVec)>, OrmError> {
+        // Los registros caducados no cuentan para el tamaño de página. La
+        // reserva inicial se acota: `n` lo elige quien llama y puede ser enorme
+        let mut iter = self.tree.range(range);
+        let mut items = Vec::with_capacity(n.min(PREALLOCATE_LIMIT));
+        while items.len() < n {
+            let item = if reverse { iter.next_back() } else { iter.next() };
+            let Some((key, value)) = item.transpose()? else {
+                break;
+            };
+            if !self.is_expired(&key)? {
+                items.push((key, value));
+            }
+        }
+        Ok(items)
+    }
+
+    fn has_after(&self, key: &[u8]) -> Result<bool, OrmError> {
+        Ok(!self.read_page((Bound::Excluded(key.to_vec()), Bound::Unbounded), 1, false)?.is_empty())
+    }
+
+    fn has_before(&self, key: &[u8]) -> Result<bool, OrmError> {
+        Ok(!self.read_page((Bound::Unbounded, Bound::Excluded(key.to_vec())), 1, true)?.is_empty())
+    }
+}
+
+impl<K, V> Page<K, V> {
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
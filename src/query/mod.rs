@@ -0,0 +1,202 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use serde::de::DeserializeOwned;
+
+use crate::{OrmError, Tree};
+
+type Filter<'a, V> = Box<dyn Fn(&V) -> bool + 'a>;
+type Comparator<'a, V> = Box<dyn Fn(&V, &V) -> Ordering + 'a>;
+
+// Consulta construida con tree.query::<V>(); no lee nada hasta llamar a
+// fetch, count, first o exists
+pub struct Query<'a, V> {
+    tree: &'a Tree,
+    filters: Vec<Filter<'a, V>>,
+    order: Option<Comparator<'a, V>>,
+    skip: usize,
+    limit: Option<usize>,
+}
+
+impl Tree {
+    pub fn query<V: DeserializeOwned>(&self) -> Query<'_, V> {
+        Query {
+            tree: self,
+            filters: Vec::new(),
+            order: None,
+            skip: 0,
+            limit: None,
+        }
+    }
+}
+
+impl<'a, V: DeserializeOwned> Query<'a, V> {
+    // Varios filtros se combinan con AND
+    pub fn filter<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&V) -> bool + 'a,
+    {
+        self.filters.push(Box::new(predicate));
+        self
+    }
+
+    pub fn order_by<K, F>(mut self, key: F) -> Self
+    where
+        K: Ord,
+        F: Fn(&V) -> K + 'a,
+    {
+        self.order = Some(Box::new(move |a, b| key(a).cmp(&key(b))));
+        self
+    }
+
+    pub fn order_by_desc<K, F>(mut self, key: F) -> Self
+    where
+        K: Ord,
+        F: Fn(&V) -> K + 'a,
+    {
+        self.order = Some(Box::new(move |a, b| key(b).cmp(&key(a))));
+        self
+    }
+
+    pub fn skip(mut self, n: usize) -> Self {
+        self.skip = n;
+        self
+    }
+
+    pub fn limit(mut self, n: usize) -> Self {
+        self.limit = Some(n);
+        self
+    }
+
+    fn matches(&self, value: &V) -> bool {
+        self.filters.iter().all(|f| f(value))
+    }
+
+    // Recorre los registros que pasan los filtros; `visit` devuelve false para cortar
+    fn scan<F>(&self, mut visit: F) -> Result<(), OrmError>
+    where
+        F: FnMut(V) -> bool,
+    {
+        for item in self.tree.iter::<V>() {
+            let (_, value) = item?;
+            if self.matches(&value) && !visit(value) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn fetch(&self) -> Result<Vec<V>, OrmError> {
+        self.fetch_limited(self.limit)
+    }
+
+    pub fn first(&self) -> Result<Option<V>, OrmError> {
+        let limit = self.limit.map_or(1, |limit| limit.min(1));
+        Ok(self.fetch_limited(Some(limit))?.pop())
+    }
+
+    fn fetch_limited(&self, limit: Option<usize>) -> Result<Vec<V>, OrmError> {
+        if limit == Some(0) {
+            return Ok(Vec::new());
+        }
+
+        let Some(order) = &self.order else {
+            // Sin orden se corta en cuanto hay suficientes resultados
+            let mut results = Vec::new();
+            let mut skipped = 0;
+            self.scan(|value| {
+                if skipped < self.skip {
+                    skipped += 1;
+                    return true;
+                }
+                results.push(value);
+                limit.is_none_or(|limit| results.len() < limit)
+            })?;
+            return Ok(results);
+        };
+
+        let mut sorted = match limit {
+            // Top-N: sólo se guardan los skip + limit primeros en un heap acotado
+            Some(limit) => {
+                let capacity = self.skip.saturating_add(limit);
+                let mut heap = BinaryHeap::new();
+                let mut seq = 0;
+                self.scan(|value| {
+                    heap.push(Ranked { value, seq, order: order.as_ref() });
+                    seq += 1;
+                    if heap.len() > capacity {
+                        heap.pop();
+                    }
+                    true
+                })?;
+                heap.into_sorted_vec().into_iter().map(|r| r.value).collect()
+            }
+            None => {
+                let mut all = Vec::new();
+                self.scan(|value| {
+                    all.push(value);
+                    true
+                })?;
+                all.sort_by(|a, b| order(a, b));
+                all
+            }
+        };
+
+        Ok(sorted.split_off(self.skip.min(sorted.len())))
+    }
+
+    // Número de registros que devolvería fetch, sin ordenarlos
+    pub fn count(&self) -> Result<usize, OrmError> {
+        let mut matched = 0;
+        let cap = self.limit.map(|limit| self.skip.saturating_add(limit));
+        if cap == Some(0) {
+            return Ok(0);
+        }
+        self.scan(|_| {
+            matched += 1;
+            cap.is_none_or(|cap| matched < cap)
+        })?;
+        Ok(matched.saturating_sub(self.skip))
+    }
+
+    pub fn exists(&self) -> Result<bool, OrmError> {
+        if self.limit == Some(0) {
+            return Ok(false);
+        }
+        let needed = self.skip.saturating_add(1);
+        let mut matched = 0;
+        self.scan(|_| {
+            matched += 1;
+            matched < needed
+        })?;
+        Ok(matched >= needed)
+    }
+}
+
+// Elemento del heap: se ordena con el comparador de la consulta y, a igualdad,
+// por orden de lectura para que el resultado sea estable
+struct Ranked<'o, V> {
+    value: V,
+    seq: usize,
+    order: &'o dyn Fn(&V, &V) -> Ordering,
+}
+
+impl<V> Ord for Ranked<'_, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.order)(&self.value, &other.value).then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl<V> PartialOrd for Ranked<'_, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<V> PartialEq for Ranked<'_, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<V> Eq for Ranked<'_, V> {}
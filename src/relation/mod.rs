@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Model, OrmError, Tree, ORM};
+
+// Qué hacer con los hijos al borrar el padre
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OnDelete {
+    // El borrado falla con OrmError::ForeignKeyViolation mientras haya hijos
+    #[default]
+    Restrict,
+    // Los hijos se borran con el padre (y los suyos, recursivamente)
+    Cascade,
+    // La clave foránea de los hijos pasa a None; sólo para campos Option
+    SetNull,
+}
+
+// Relación N:1 de un modelo hijo con su padre. Normalmente la genera el
+// derive a partir de un campo marcado con #[belongs_to(Padre)]:
+//
+// #[derive(Serialize, Deserialize, Model)]
+// #[model(tree = "members")]
+// struct Member {
+//     #[primary_key]
+//     id: u64,
+//     #[belongs_to(Guild, on_delete = "cascade")]
+//     guild_id: u64,
+// }
+//
+// El campo puede ser Option para permitir hijos sin padre. El índice inverso
+// se guarda como un índice secundario con el nombre del campo, así los hijos
+// de un padre se encuentran sin recorrer el árbol. Escribir un hijo cuyo
+// padre no existe falla con OrmError::MissingParent.
+pub trait BelongsTo<P: Model>: Model {
+    // Nombre del campo con la clave del padre; también es el nombre del índice
+    const FOREIGN_KEY: &'static str;
+
+    const ON_DELETE: OnDelete = OnDelete::Restrict;
+
+    fn parent_key(&self) -> Option<P::PrimaryKey>;
+
+    // Lo usa OnDelete::SetNull; el derive lo genera para los campos Option
+    fn clear_parent_key(&mut self) {}
+
+    fn parent(&self, orm: &ORM) -> Result<Option<P>, OrmError> {
+        match self.parent_key() {
+            Some(key) => P::load(orm, &key),
+            None => Ok(None),
+        }
+    }
+
+    fn children_of(parent: &P, orm: &ORM) -> Result<Vec<Self>, OrmError> {
+        Self::tree(orm)?.find_by_index(Self::FOREIGN_KEY, parent.key())
+    }
+}
+
+// Hijo que impidió borrar un registro con OnDelete::Restrict
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockingReference {
+    pub tree: String,
+    pub foreign_key: String,
+    pub key: Vec<u8>,
+}
+
+type SetNull = dyn Fn(&Tree, &[u8], &[u8]) -> Result<Vec<u8>, OrmError> + Send + Sync;
+
+// Referencia hacia un árbol padre, guardada en el esquema del padre para
+// que los borrados sepan qué hijos revisar (ver TypedTx::write)
+pub(crate) struct Reference {
+    pub(crate) child: String,
+    pub(crate) foreign_key: String,
+    pub(crate) on_delete: OnDelete,
+    // None si se cargó de __meta y el modelo hijo aún no se ha configurado
+    // en esta conexión
+    pub(crate) set_null: Option<Box<SetNull>>,
+}
+
+// Cómo se guarda una referencia en __meta, para que el padre la conozca
+// aunque el hijo no se abra
+#[derive(Serialize, Deserialize)]
+struct StoredReference {
+    parent: String,
+    child: String,
+    foreign_key: String,
+    on_delete: OnDelete,
+}
+
+fn reference_prefix(parent: &str) -> String {
+    format!("reference:{}:", parent)
+}
+
+impl Tree {
+    // Registra el índice inverso de la relación sobre el árbol del hijo y su
+    // restricción en el esquema del padre, y la guarda en __meta; el derive
+    // lo llama desde Model::configure
+    pub fn belongs_to<C, P>(&self) -> Result<(), OrmError>
+    where
+        C: BelongsTo<P> + 'static,
+        P: Model,
+    {
+        self.register_index(C::FOREIGN_KEY, false, Some(P::TREE.to_string()), |child: &C| child.parent_key())?;
+        let stored = StoredReference {
+            parent: P::TREE.to_string(),
+            child: self.name(),
+            foreign_key: C::FOREIGN_KEY.to_string(),
+            on_delete: C::ON_DELETE,
+        };
+        let key = format!("{}{}:{}", reference_prefix(P::TREE), stored.child, stored.foreign_key);
+        self.conn.get_orm().meta()?.insert(key, &stored)?;
+        self.conn.registry.schema(P::TREE).add_reference(Reference {
+            child: self.name(),
+            foreign_key: C::FOREIGN_KEY.to_string(),
+            on_delete: C::ON_DELETE,
+            set_null: Some(Box::new(|tree, key, bytes| {
+                let mut child: C = tree.decode(key, bytes)?;
+                child.clear_parent_key();
+                tree.encode(key, &child)
+            })),
+        });
+        Ok(())
+    }
+
+    // Configura el modelo hijo si esta conexión aún no lo hizo; sin él los
+    // borrados en cascada o con SetNull fallan con OrmError::UnloadedRelation
+    pub fn has_many<C, P>(&self) -> Result<(), OrmError>
+    where
+        C: BelongsTo<P>,
+        P: Model,
+    {
+        if !self.schema.has_reference(C::TREE, C::FOREIGN_KEY) {
+            C::tree(&self.conn.get_orm())?;
+        }
+        Ok(())
+    }
+
+    // Al abrir un árbol, las referencias que otros modelos guardaron hacia él
+    pub(crate) fn load_references(&self) -> Result<(), OrmError> {
+        let name = self.name();
+        if name.starts_with("__") {
+            return Ok(());
+        }
+        let meta = self.conn.get_orm().meta()?;
+        for item in meta.tree.scan_prefix(reference_prefix(&name)) {
+            let (key, bytes) = item?;
+            let stored: StoredReference = meta.decode(&key, &bytes)?;
+            if stored.parent != name {
+                continue;
+            }
+            self.schema.add_reference(Reference {
+                child: stored.child,
+                foreign_key: stored.foreign_key,
+                on_delete: stored.on_delete,
+                set_null: None,
+            });
+        }
+        Ok(())
+    }
+}
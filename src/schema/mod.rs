@@ -0,0 +1,222 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::cas::Versioning;
+use crate::codec::CodecKind;
+use crate::compression::Compression;
+use crate::encryption::Keyring;
+use crate::hooks::Hooks;
+use crate::index::Index;
+use crate::keygen::{KeyGenerator, KeyStrategy};
+use crate::migration::TreeMigrations;
+use crate::relation::Reference;
+use crate::ttl::Ttl;
+
+// Configuración compartida por todas las instancias de Tree con el mismo nombre.
+// Vive en la Connection para que orm.tree("users") devuelva siempre el mismo esquema.
+#[derive(Default)]
+pub(crate) struct Registry {
+    trees: RwLock<HashMap<String, Arc<TreeSchema>>>,
+    // Las escrituras lo toman en lectura desde que eligen con qué índices
+    // escriben hasta que confirman; cambiar los índices de un árbol lo toma
+    // en escritura, así nadie sigue escribiendo con la lista vieja
+    writes: RwLock<()>,
+}
+
+impl Registry {
+    pub(crate) fn writing(&self) -> RwLockReadGuard<'_, ()> {
+        self.writes.read().unwrap()
+    }
+
+    pub(crate) fn changing_indexes(&self) -> RwLockWriteGuard<'_, ()> {
+        self.writes.write().unwrap()
+    }
+
+    pub(crate) fn schema(&self, name: &str) -> Arc<TreeSchema> {
+        if let Some(schema) = self.trees.read().unwrap().get(name) {
+            return schema.clone();
+        }
+        self.trees
+            .write()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct TreeSchema {
+    indexes: RwLock<Vec<Arc<Index>>>,
+    pub(crate) migrations: TreeMigrations,
+    codec: RwLock<Option<CodecKind>>,
+    compression: RwLock<Option<Compression>>,
+    encryption: RwLock<Option<Arc<Keyring>>>,
+    ttl: RwLock<Option<Arc<Ttl>>>,
+    hooks: RwLock<Arc<Hooks>>,
+    references: RwLock<Vec<Arc<Reference>>>,
+    keys: RwLock<Option<Arc<KeyGenerator>>>,
+    versioning: RwLock<Option<Arc<Versioning>>>,
+    // Sólo se construye un índice a la vez por árbol
+    pub(crate) index_builds: Mutex<()>,
+    // ORM::tree ya cargó lo que hay guardado en disco (caducidades...)
+    loaded: AtomicBool,
+    // Modelos cuyo Model::configure ya se ejecutó sobre este árbol
+    configured: Mutex<HashSet<&'static str>>,
+}
+
+impl TreeSchema {
+    pub(crate) fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn mark_loaded(&self) {
+        self.loaded.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn is_configured(&self, model: &str) -> bool {
+        self.configured.lock().unwrap().contains(model)
+    }
+
+    pub(crate) fn mark_configured(&self, model: &'static str) {
+        self.configured.lock().unwrap().insert(model);
+    }
+
+    // Incluye los índices que se están construyendo: las escrituras tienen
+    // que mantenerlos aunque todavía no se puedan consultar
+    pub(crate) fn indexes(&self) -> Vec<Arc<Index>> {
+        self.indexes.read().unwrap().clone()
+    }
+
+    pub(crate) fn has_indexes(&self) -> bool {
+        !self.indexes.read().unwrap().is_empty()
+    }
+
+    // Con índices, caducidades, hooks, referencias o versiones las
+    // escrituras tienen que ir en una transacción
+    pub(crate) fn needs_transaction(&self) -> bool {
+        self.has_indexes()
+            || self.ttl.read().unwrap().is_some()
+            || !self.hooks.read().unwrap().is_empty()
+            || self.has_references()
+            || self.versioning.read().unwrap().is_some()
+    }
+
+    pub(crate) fn versioning(&self) -> Option<Arc<Versioning>> {
+        self.versioning.read().unwrap().clone()
+    }
+
+    pub(crate) fn set_versioning(&self, versioning: Versioning) {
+        *self.versioning.write().unwrap() = Some(Arc::new(versioning));
+    }
+
+    pub(crate) fn hooks(&self) -> Arc<Hooks> {
+        self.hooks.read().unwrap().clone()
+    }
+
+    // Las escrituras en curso siguen usando la copia que ya tenían
+    pub(crate) fn update_hooks(&self, f: impl FnOnce(&mut Hooks)) {
+        let mut hooks = self.hooks.write().unwrap();
+        f(Arc::make_mut(&mut hooks));
+    }
+
+    // Referencias de otros árboles hacia este
+    pub(crate) fn references(&self) -> Vec<Arc<Reference>> {
+        self.references.read().unwrap().clone()
+    }
+
+    pub(crate) fn has_references(&self) -> bool {
+        !self.references.read().unwrap().is_empty()
+    }
+
+    // Sólo cuenta si el modelo hijo ya se configuró en esta conexión
+    pub(crate) fn has_reference(&self, child: &str, foreign_key: &str) -> bool {
+        self.references
+            .read()
+            .unwrap()
+            .iter()
+            .any(|r| r.child == child && r.foreign_key == foreign_key && r.set_null.is_some())
+    }
+
+    // La referencia completa de belongs_to sustituye a la cargada de __meta
+    pub(crate) fn add_reference(&self, reference: Reference) {
+        let mut references = self.references.write().unwrap();
+        match references.iter().position(|r| r.child == reference.child && r.foreign_key == reference.foreign_key) {
+            Some(i) if references[i].set_null.is_none() && reference.set_null.is_some() => {
+                references[i] = Arc::new(reference)
+            }
+            Some(_) => {}
+            None => references.push(Arc::new(reference)),
+        }
+    }
+
+    pub(crate) fn ttl(&self) -> Option<Arc<Ttl>> {
+        self.ttl.read().unwrap().clone()
+    }
+
+    // Si otro hilo se adelantó se conserva el suyo
+    pub(crate) fn set_ttl(&self, ttl: Ttl) {
+        self.ttl.write().unwrap().get_or_insert_with(|| Arc::new(ttl));
+    }
+
+    // Sólo los índices ya construidos
+    pub(crate) fn index(&self, name: &str) -> Option<Arc<Index>> {
+        self.indexes.read().unwrap().iter().find(|i| i.name == name && i.is_ready()).cloned()
+    }
+
+    // Sin estrategia fijada se usa la por defecto (Sequence)
+    pub(crate) fn key_generator(&self) -> Arc<KeyGenerator> {
+        if let Some(generator) = self.keys.read().unwrap().as_ref() {
+            return generator.clone();
+        }
+        self.keys
+            .write()
+            .unwrap()
+            .get_or_insert_with(|| Arc::new(KeyGenerator::new(KeyStrategy::default())))
+            .clone()
+    }
+
+    // Si la estrategia no cambia se conserva el generador (y su estado)
+    pub(crate) fn set_key_strategy(&self, strategy: KeyStrategy) {
+        let mut keys = self.keys.write().unwrap();
+        if keys.as_ref().is_none_or(|generator| generator.strategy != strategy) {
+            *keys = Some(Arc::new(KeyGenerator::new(strategy)));
+        }
+    }
+
+    pub(crate) fn codec(&self) -> Option<CodecKind> {
+        self.codec.read().unwrap().clone()
+    }
+
+    pub(crate) fn set_codec(&self, codec: CodecKind) {
+        *self.codec.write().unwrap() = Some(codec);
+    }
+
+    pub(crate) fn compression(&self) -> Option<Compression> {
+        *self.compression.read().unwrap()
+    }
+
+    pub(crate) fn set_compression(&self, compression: Compression) {
+        *self.compression.write().unwrap() = Some(compression);
+    }
+
+    pub(crate) fn encryption(&self) -> Option<Arc<Keyring>> {
+        self.encryption.read().unwrap().clone()
+    }
+
+    pub(crate) fn set_encryption(&self, encryption: Arc<Keyring>) {
+        *self.encryption.write().unwrap() = Some(encryption);
+    }
+
+    pub(crate) fn add_index(&self, index: Arc<Index>) {
+        let mut indexes = self.indexes.write().unwrap();
+        if !indexes.iter().any(|i| i.name == index.name) {
+            indexes.push(index);
+        }
+    }
+
+    pub(crate) fn remove_index(&self, name: &str) {
+        self.indexes.write().unwrap().retain(|i| i.name != name);
+    }
+}
@@ -0,0 +1,116 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use sled::{Event, IVec};
+
+use crate::{Key, OrmError, Tree};
+
+// Cambio en un registro observado con Tree::subscribe
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<K, V> {
+    Inserted { key: K, value: V },
+    Removed { key: K },
+}
+
+impl<K, V> Change<K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Inserted { key, .. } | Change::Removed { key } => key,
+        }
+    }
+}
+
+// Eventos de sled decodificados con el codec (y la compresión y el cifrado)
+// del árbol. Como iterador bloquea hasta el siguiente cambio; con la feature
+// `async`, into_stream lo convierte en un Stream.
+pub struct Subscription<K, V> {
+    tree: Tree,
+    inner: sled::Subscriber,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> Subscription<K, V>
+where
+    K: Key,
+    V: DeserializeOwned,
+{
+    fn decode(&self, event: Event) -> Result<Change<K, V>, OrmError> {
+        match event {
+            Event::Insert { key, value } => Ok(Change::Inserted {
+                value: self.tree.decode(&key, &value)?,
+                key: self.tree.decode_key(&key)?,
+            }),
+            Event::Remove { key } => Ok(Change::Removed { key: self.tree.decode_key(&key)? }),
+        }
+    }
+
+    // None si no llegó ningún cambio a tiempo o el árbol se cerró
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Change<K, V>, OrmError>> {
+        let event = self.inner.next_timeout(timeout).ok()?;
+        Some(self.decode(event))
+    }
+}
+
+impl<K, V> Iterator for Subscription<K, V>
+where
+    K: Key,
+    V: DeserializeOwned,
+{
+    type Item = Result<Change<K, V>, OrmError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.inner.next()?;
+        Some(self.decode(event))
+    }
+}
+
+// Versión asíncrona de Subscription; es un tipo aparte para que `next` no
+// sea ambiguo entre Iterator y StreamExt
+#[cfg(feature = "async")]
+pub struct ChangeStream<K, V> {
+    subscription: Subscription<K, V>,
+}
+
+#[cfg(feature = "async")]
+impl<K, V> Subscription<K, V> {
+    pub fn into_stream(self) -> ChangeStream<K, V> {
+        ChangeStream { subscription: self }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K, V> futures_core::Stream for ChangeStream<K, V>
+where
+    K: Key,
+    V: DeserializeOwned,
+{
+    type Item = Result<Change<K, V>, OrmError>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let subscription = &mut self.subscription;
+        std::future::Future::poll(std::pin::Pin::new(&mut subscription.inner), cx)
+            .map(|event| event.map(|event| subscription.decode(event)))
+    }
+}
+
+impl Tree {
+    // Cambios en las claves que empiezan por `prefix` (todas si está vacío) a
+    // partir de ahora. Los registros que caducan no generan Removed hasta que
+    // se purgan.
+    pub fn subscribe<V: DeserializeOwned>(&self, prefix: impl AsRef<[u8]>) -> Subscription<IVec, V> {
+        self.subscribe_as(prefix)
+    }
+
+    pub(crate) fn subscribe_as<K, V, P>(&self, prefix: P) -> Subscription<K, V>
+    where
+        K: Key,
+        V: DeserializeOwned,
+        P: AsRef<[u8]>,
+    {
+        Subscription { tree: self.clone(), inner: self.tree.watch_prefix(prefix), marker: PhantomData }
+    }
+}
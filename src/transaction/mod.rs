@@ -0,0 +1,678 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::{Arc, RwLockReadGuard};
+
+use serde::{de::DeserializeOwned, Serialize};
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionalTree, Transactional},
+    IVec,
+};
+
+use crate::{
+    cas::Versioning,
+    hooks::Hooks,
+    index::{self, Index},
+    relation::{OnDelete, Reference},
+    ttl, BlockingReference, Connection, OrmError, Tree, ORM,
+};
+
+pub type TxResult<T> = Result<T, ConflictableTransactionError<OrmError>>;
+
+enum Written {
+    Saved(Vec<u8>),
+    Deleted(IVec),
+}
+
+// Hijos de un registro por una relación: (árbol hijo, clave foránea, clave del padre)
+type Children = (String, String, Vec<u8>);
+
+// Hijos leídos del índice inverso fuera de la transacción, con el contador
+// que tenía entonces; se conservan entre los intentos de una transacción
+type Lookups = HashMap<Children, (Option<IVec>, Vec<IVec>)>;
+
+// Cambios de este intento en los hijos de un registro
+#[derive(Default)]
+struct Touched {
+    // Contador antes del primer cambio
+    base: Option<IVec>,
+    added: BTreeSet<Vec<u8>>,
+    removed: BTreeSet<Vec<u8>>,
+}
+
+// Un Tree de la transacción: lo que hay que mantener al escribir en él, las
+// referencias hacia él y su tramo en la lista de árboles sled
+struct Part {
+    tree: Tree,
+    set: TreeSet,
+    references: Vec<Arc<Reference>>,
+    range: Range<usize>,
+}
+
+// Todos los árboles de una transacción: los pedidos, los de sus hijos (para
+// aplicar on_delete), los de sus padres (para comprobar que existen) y los
+// índices inversos de hijos cuyo modelo no se ha configurado
+struct Scope {
+    parts: Vec<Part>,
+    unloaded: HashMap<(String, String), usize>,
+    trees: Vec<sled::Tree>,
+}
+
+impl Scope {
+    // Abre los árboles relacionados con `requested` sin el candado de
+    // escrituras (abrir un árbol puede migrarlo) y después, ya con él, fija
+    // sus índices y referencias. Si entretanto cambiaron se vuelve a empezar.
+    fn open<'c>(conn: &'c Connection, requested: &[&Tree]) -> Result<(Scope, RwLockReadGuard<'c, ()>), OrmError> {
+        let orm = conn.get_orm();
+        // (árbol, si se siguen sus relaciones); de los padres sólo se lee
+        let mut trees: Vec<(Tree, bool)> = requested.iter().map(|tree| ((*tree).clone(), true)).collect();
+        loop {
+            let mut pending: Vec<usize> = (0..trees.len()).filter(|&i| trees[i].1).collect();
+            while let Some(i) = pending.pop() {
+                let schema = trees[i].0.schema.clone();
+                let children = schema.references().into_iter().map(|r| (r.child.clone(), true));
+                let parents = schema.indexes().into_iter().filter_map(|i| i.parent.clone()).map(|p| (p, false));
+                for (name, follow) in children.chain(parents).collect::<Vec<_>>() {
+                    match trees.iter().position(|(tree, _)| tree.name() == name) {
+                        Some(j) if follow && !trees[j].1 => {
+                            trees[j].1 = true;
+                            pending.push(j);
+                        }
+                        Some(_) => {}
+                        None => {
+                            trees.push((orm.tree(&name)?, follow));
+                            if follow {
+                                pending.push(trees.len() - 1);
+                            }
+                        }
+                    }
+                }
+            }
+
+            let writing = conn.registry.writing();
+            if let Some(scope) = Scope::build(conn, &trees)? {
+                return Ok((scope, writing));
+            }
+        }
+    }
+
+    // None si algún árbol necesita otro que no se abrió
+    fn build(conn: &Connection, trees: &[(Tree, bool)]) -> Result<Option<Scope>, OrmError> {
+        let mut all = Vec::new();
+        let mut parts = Vec::new();
+        for (tree, _) in trees {
+            let set = tree.transaction_trees();
+            let range = all.len()..all.len() + set.trees.len();
+            all.extend(set.trees.iter().cloned());
+            parts.push(Part { tree: tree.clone(), references: tree.schema.references(), set, range });
+        }
+        let mut scope = Scope { parts, unloaded: HashMap::new(), trees: all };
+
+        for (i, (_, follow)) in trees.iter().enumerate() {
+            if !follow {
+                continue;
+            }
+            for parent in scope.parts[i].set.indexes.iter().filter_map(|index| index.parent.as_deref()) {
+                if scope.position(parent).is_none() {
+                    return Ok(None);
+                }
+            }
+            for reference in scope.parts[i].references.clone() {
+                let Some(child) = scope.position(&reference.child).filter(|&child| trees[child].1) else {
+                    return Ok(None);
+                };
+                let id = (reference.child.clone(), reference.foreign_key.clone());
+                if scope.parts[child].set.indexes.iter().any(|index| index.name == reference.foreign_key)
+                    || scope.unloaded.contains_key(&id)
+                {
+                    continue;
+                }
+                scope.unloaded.insert(id, scope.trees.len());
+                scope.trees.push(conn.db.open_tree(Index::tree_name(&reference.child, &reference.foreign_key))?);
+            }
+        }
+        Ok(Some(scope))
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.parts.iter().position(|part| part.tree.name() == name)
+    }
+
+    // Posición en la lista sled del índice inverso de `reference` y si ya se
+    // puede consultar
+    fn reverse_index(&self, reference: &Reference) -> (usize, bool) {
+        let child = &self.parts[self.position(&reference.child).expect("children are part of the transaction")];
+        match child.set.indexes.iter().position(|index| index.name == reference.foreign_key) {
+            Some(i) => (child.range.start + 1 + i, child.set.indexes[i].is_ready()),
+            None => (self.unloaded[&(reference.child.clone(), reference.foreign_key.clone())], true),
+        }
+    }
+}
+
+// Estado de un intento de la transacción, compartido por sus TypedTx
+struct Ctx {
+    scope: Rc<Scope>,
+    txs: Vec<TransactionalTree>,
+    // Escrituras cuyo after_* se lanza al confirmar: (Part, clave, qué pasó)
+    pending: RefCell<Vec<(usize, Vec<u8>, Written)>>,
+    touched: RefCell<HashMap<Children, Touched>>,
+    lookups: Rc<RefCell<Lookups>>,
+    // Hijos que hay que leer fuera antes de repetir la transacción
+    missing: RefCell<Vec<Children>>,
+}
+
+// Vista tipada de un Tree dentro de una transacción. Los valores se
+// codifican igual que en Tree::insert y los índices del árbol (y sus
+// caducidades) se actualizan en la misma transacción. Los hooks before_*
+// se ejecutan dentro de la transacción (así que no pueden leer ni escribir
+// en la base) y los after_* cuando se confirma. Las referencias y las
+// versiones se comprueban igual que en Tree::insert y Tree::delete.
+pub struct TypedTx<'a> {
+    ctx: Rc<Ctx>,
+    part: usize,
+    trees: PhantomData<&'a Tree>,
+}
+
+impl<'a> TypedTx<'a> {
+    pub fn get<K, V>(&self, key: K) -> TxResult<Option<V>>
+    where
+        K: AsRef<[u8]>,
+        V: DeserializeOwned,
+    {
+        if self.is_expired(key.as_ref())? {
+            return Ok(None);
+        }
+        match self.txs()[0].get(key.as_ref())? {
+            Some(bytes) => Ok(Some(self.tree().decode(key.as_ref(), &bytes).map_err(ConflictableTransactionError::Abort)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn insert<K, V>(&self, key: K, value: &V) -> TxResult<()>
+    where
+        K: AsRef<[u8]>,
+        V: Serialize,
+    {
+        let bytes = self.tree().encode(key.as_ref(), value).map_err(ConflictableTransactionError::Abort)?;
+        self.write(key.as_ref(), Some(&bytes))?;
+        Ok(())
+    }
+
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> TxResult<()> {
+        self.write(key.as_ref(), None)?;
+        Ok(())
+    }
+
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> TxResult<bool> {
+        Ok(self.txs()[0].get(key.as_ref())?.is_some() && !self.is_expired(key.as_ref())?)
+    }
+
+    // Aborta la transacción devolviendo `err` al llamador de Tree::transaction
+    pub fn abort<T>(&self, err: impl Into<OrmError>) -> TxResult<T> {
+        Err(ConflictableTransactionError::Abort(err.into()))
+    }
+
+    pub fn tree_name(&self) -> String {
+        self.tree().name()
+    }
+
+    fn part(&self) -> &Part {
+        &self.ctx.scope.parts[self.part]
+    }
+
+    fn tree(&self) -> &Tree {
+        &self.part().tree
+    }
+
+    fn txs(&self) -> &[TransactionalTree] {
+        &self.ctx.txs[self.part().range.clone()]
+    }
+
+    fn view(&self, part: usize) -> TypedTx<'a> {
+        TypedTx { ctx: self.ctx.clone(), part, trees: PhantomData }
+    }
+
+    pub(crate) fn get_raw(&self, key: &[u8]) -> TxResult<Option<IVec>> {
+        Ok(self.txs()[0].get(key)?)
+    }
+
+    // Escribe (o borra, si `value` es None) pasando por los hooks del árbol.
+    // Las claves foráneas nuevas tienen que apuntar a un padre que exista y
+    // al borrar se aplica el on_delete de cada referencia hacia el registro.
+    pub(crate) fn write(&self, key: &[u8], value: Option<&[u8]>) -> TxResult<Option<IVec>> {
+        let mut blocking = Vec::new();
+        let old = self.write_checked(key, value, &mut blocking)?;
+
+        // Los hijos que la misma cascada acabó borrando ya no bloquean
+        let mut references = Vec::new();
+        for (part, reference) in blocking {
+            if self.view(part).contains_key(&reference.key)? {
+                references.push(reference);
+            }
+        }
+        if !references.is_empty() {
+            return self.abort(OrmError::ForeignKeyViolation { tree: self.tree_name(), key: key.to_vec(), references });
+        }
+        Ok(old)
+    }
+
+    fn write_checked(
+        &self,
+        key: &[u8],
+        value: Option<&[u8]>,
+        blocking: &mut Vec<(usize, BlockingReference)>,
+    ) -> TxResult<Option<IVec>> {
+        let abort = ConflictableTransactionError::Abort;
+        let hooks = &self.part().set.hooks;
+        let value = match value {
+            Some(bytes) if !hooks.is_empty() => Some(hooks.before_save(self.tree(), key, bytes.to_vec()).map_err(abort)?),
+            Some(bytes) => Some(bytes.to_vec()),
+            None => {
+                if !hooks.is_empty()
+                    && let Some(current) = self.get_raw(key)?
+                {
+                    hooks.before_delete(self.tree(), key, &current).map_err(abort)?;
+                }
+                None
+            }
+        };
+
+        let value = match (value, &self.part().set.versioning) {
+            (Some(bytes), Some(versioning)) => {
+                let stored = if self.is_expired(key)? { None } else { self.get_raw(key)? };
+                Some(versioning.next(self.tree(), key, stored.as_deref(), &bytes).map_err(abort)?)
+            }
+            (value, _) => value,
+        };
+
+        let old = self.apply(key, value.as_deref())?;
+        if value.is_none() && old.is_some() {
+            self.apply_references(key, blocking)?;
+        }
+
+        if !hooks.is_empty() {
+            let written = match (value, &old) {
+                (Some(bytes), _) => Written::Saved(bytes),
+                (None, Some(old)) => Written::Deleted(old.clone()),
+                (None, None) => return Ok(old),
+            };
+            self.ctx.pending.borrow_mut().push((self.part, key.to_vec(), written));
+        }
+        Ok(old)
+    }
+
+    // Sólo para las migraciones: cambia los bytes del registro si siguen
+    // siendo `old`, sin tocar índices, caducidad ni hooks
+    pub(crate) fn migrate_raw(&self, key: &[u8], old: &[u8], new: &[u8]) -> TxResult<bool> {
+        let primary = &self.txs()[0];
+        if primary.get(key)?.as_deref() != Some(old) {
+            return Ok(false);
+        }
+        primary.insert(key, new)?;
+        Ok(true)
+    }
+
+    // Cualquier escritura quita la caducidad que tuviera la clave
+    fn apply(&self, key: &[u8], value: Option<&[u8]>) -> TxResult<Option<IVec>> {
+        let (part, txs) = (self.part(), self.txs());
+        let ttl = self.ttl_trees().map(|(by_key, _)| by_key);
+        let old = index::apply(&part.tree, &part.set.indexes, txs, ttl, key, value, |i, parent_key, added| {
+            let index = &part.set.indexes[i];
+            let Some(parent) = &index.parent else {
+                return Ok(());
+            };
+            let previous = Index::bump_generation(&txs[i + 1], parent_key)?;
+            {
+                let mut touched = self.ctx.touched.borrow_mut();
+                let touched = touched
+                    .entry((part.tree.name(), index.name.clone(), parent_key.to_vec()))
+                    .or_insert_with(|| Touched { base: previous, ..Touched::default() });
+                if added {
+                    touched.removed.remove(key);
+                    touched.added.insert(key.to_vec());
+                } else {
+                    touched.added.remove(key);
+                    touched.removed.insert(key.to_vec());
+                }
+            }
+            if added {
+                self.check_parent(key, index, parent, parent_key)?;
+            }
+            Ok(())
+        })?;
+        if let Some((by_key, by_time)) = self.ttl_trees() {
+            ttl::clear(by_key, by_time, key)?;
+        }
+        Ok(old)
+    }
+
+    fn check_parent(&self, key: &[u8], index: &Index, parent: &str, parent_key: &[u8]) -> TxResult<()> {
+        let position = self.ctx.scope.position(parent).expect("parents are part of the transaction");
+        if self.view(position).contains_key(parent_key)? {
+            return Ok(());
+        }
+        self.abort(OrmError::MissingParent {
+            tree: self.tree_name(),
+            key: key.to_vec(),
+            foreign_key: index.name.clone(),
+            parent_tree: parent.to_string(),
+            parent_key: parent_key.to_vec(),
+        })
+    }
+
+    // Aplica el on_delete de cada referencia hacia `key`, ya borrado. Los
+    // hijos caducados se ignoran, igual que en las lecturas.
+    fn apply_references(&self, key: &[u8], blocking: &mut Vec<(usize, BlockingReference)>) -> TxResult<()> {
+        for reference in &self.part().references {
+            let child = self.view(self.ctx.scope.position(&reference.child).expect("children are part of the transaction"));
+            let mut live = Vec::new();
+            for child_key in self.children(reference, key)? {
+                if child.contains_key(&child_key)? {
+                    live.push(child_key);
+                }
+            }
+            if live.is_empty() {
+                continue;
+            }
+
+            match (reference.on_delete, &reference.set_null) {
+                (OnDelete::Restrict, _) => blocking.extend(live.into_iter().map(|child_key| {
+                    let reference = BlockingReference {
+                        tree: reference.child.clone(),
+                        foreign_key: reference.foreign_key.clone(),
+                        key: child_key,
+                    };
+                    (child.part, reference)
+                })),
+                (_, None) => {
+                    return self.abort(OrmError::UnloadedRelation {
+                        tree: reference.child.clone(),
+                        foreign_key: reference.foreign_key.clone(),
+                    })
+                }
+                (OnDelete::Cascade, Some(_)) => {
+                    for child_key in live {
+                        child.write_checked(&child_key, None, blocking)?;
+                    }
+                }
+                (OnDelete::SetNull, Some(set_null)) => {
+                    for child_key in live {
+                        let Some(current) = child.get_raw(&child_key)? else {
+                            continue;
+                        };
+                        let bytes = set_null(child.tree(), &child_key, &current).map_err(ConflictableTransactionError::Abort)?;
+                        child.write_checked(&child_key, Some(&bytes), blocking)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Hijos actuales de `parent_key` por `reference`. sled no deja recorrer
+    // el índice dentro de la transacción, así que se usa lo que run leyó
+    // fuera si su contador sigue igual (más lo que cambió este intento); si
+    // no, se pide leerlo y se repite la transacción.
+    fn children(&self, reference: &Reference, parent_key: &[u8]) -> TxResult<Vec<Vec<u8>>> {
+        let (position, ready) = self.ctx.scope.reverse_index(reference);
+        let id = (reference.child.clone(), reference.foreign_key.clone(), parent_key.to_vec());
+        let touched = self.ctx.touched.borrow();
+        let touched = touched.get(&id);
+        let base = match touched {
+            Some(touched) => touched.base.clone(),
+            None => self.ctx.txs[position].get(Index::generation_key(parent_key))?,
+        };
+
+        match self.ctx.lookups.borrow().get(&id) {
+            Some((generation, keys)) if ready && *generation == base => {
+                let mut children: BTreeSet<Vec<u8>> = keys.iter().map(|key| key.to_vec()).collect();
+                if let Some(touched) = touched {
+                    children.retain(|key| !touched.removed.contains(key));
+                    children.extend(touched.added.iter().cloned());
+                }
+                Ok(children.into_iter().collect())
+            }
+            _ => {
+                self.ctx.missing.borrow_mut().push(id);
+                self.abort("children changed since they were looked up")
+            }
+        }
+    }
+
+    // Los árboles de caducidad van al final, detrás de los de los índices
+    fn ttl_trees(&self) -> Option<(&TransactionalTree, &TransactionalTree)> {
+        let txs = self.txs();
+        let n = txs.len();
+        self.part().set.ttl.then(|| (&txs[n - 2], &txs[n - 1]))
+    }
+
+    pub(crate) fn is_expired(&self, key: &[u8]) -> TxResult<bool> {
+        match self.ttl_trees() {
+            Some((by_key, _)) => Ok(ttl::expired(by_key.get(key)?)),
+            None => Ok(false),
+        }
+    }
+
+    pub(crate) fn expiry(&self, key: &[u8]) -> TxResult<Option<u64>> {
+        match self.ttl_trees() {
+            Some((by_key, _)) => Ok(by_key.get(key)?.as_deref().and_then(ttl::read_expiry)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn set_expiry(&self, key: &[u8], expires_at: u64) -> TxResult<()> {
+        match self.ttl_trees() {
+            Some((by_key, by_time)) => ttl::set(by_key, by_time, key, expires_at),
+            None => self.abort(format!("tree `{}` has no ttl trees", self.tree_name())),
+        }
+    }
+
+    pub(crate) fn remove_stale_expiry(&self, entry: &[u8]) -> TxResult<()> {
+        if let Some((_, by_time)) = self.ttl_trees() {
+            by_time.remove(entry)?;
+        }
+        Ok(())
+    }
+}
+
+// Árboles sled que participan en una transacción sobre un Tree: el primario,
+// los de sus índices y, si tiene registros con caducidad, los de TTL
+pub(crate) struct TreeSet {
+    indexes: Vec<Arc<Index>>,
+    ttl: bool,
+    hooks: Arc<Hooks>,
+    versioning: Option<Arc<Versioning>>,
+    pub(crate) trees: Vec<sled::Tree>,
+}
+
+impl Tree {
+    // Árbol primario seguido de los árboles de sus índices y de TTL, en el
+    // orden que espera TypedTx
+    pub(crate) fn transaction_trees(&self) -> TreeSet {
+        let indexes = self.schema.indexes();
+        let ttl = self.schema.ttl();
+        let mut trees = vec![self.tree.clone()];
+        trees.extend(indexes.iter().map(|i| i.tree.clone()));
+        if let Some(ttl) = &ttl {
+            trees.extend([ttl.by_key.clone(), ttl.by_time.clone()]);
+        }
+        TreeSet { indexes, ttl: ttl.is_some(), hooks: self.schema.hooks(), versioning: self.schema.versioning(), trees }
+    }
+
+    pub fn transaction<F, T>(&self, f: F) -> Result<T, OrmError>
+    where
+        F: Fn(&TypedTx) -> TxResult<T>,
+    {
+        run(&self.conn, &[self], |txs| f(&txs[0]))
+    }
+}
+
+// Ejecuta `f` con un TypedTx por árbol y, si la transacción se confirma,
+// lanza los after_* de las escrituras del último intento (sled repite el
+// closure si hay conflictos, así que los de intentos fallidos se descartan).
+// Si un intento necesitó hijos que no se habían leído, se leen fuera de la
+// transacción y se repite.
+fn run<'a, F, T>(conn: &Connection, trees: &[&'a Tree], f: F) -> Result<T, OrmError>
+where
+    F: Fn(Vec<TypedTx<'a>>) -> TxResult<T>,
+{
+    let lookups: Rc<RefCell<Lookups>> = Rc::default();
+    loop {
+        let (scope, writing) = Scope::open(conn, trees)?;
+        let scope = Rc::new(scope);
+        let missing = RefCell::new(Vec::new());
+        let committed = RefCell::new(Vec::new());
+
+        let result = Transactional::<OrmError>::transaction(scope.trees.as_slice(), |txs| {
+            let ctx = Rc::new(Ctx {
+                scope: scope.clone(),
+                txs: txs.to_vec(),
+                pending: RefCell::default(),
+                touched: RefCell::default(),
+                lookups: lookups.clone(),
+                missing: RefCell::default(),
+            });
+            let views = (0..trees.len()).map(|part| TypedTx { ctx: ctx.clone(), part, trees: PhantomData }).collect();
+            let value = f(views);
+            // Aunque `f` se tragara el error, el intento no vale
+            missing.replace(ctx.missing.take());
+            if !missing.borrow().is_empty() {
+                return Err(ConflictableTransactionError::Abort(OrmError::Aborted("children not looked up".into())));
+            }
+            let value = value?;
+            committed.replace(ctx.pending.take());
+            Ok(value)
+        });
+        drop(writing);
+
+        let missing = missing.take();
+        if !missing.is_empty() {
+            look_up(conn, missing, &mut lookups.borrow_mut())?;
+            continue;
+        }
+        let result = result?;
+
+        for (part, key, written) in committed.take() {
+            let part = &scope.parts[part];
+            match written {
+                Written::Saved(bytes) => part.set.hooks.after_save(&part.tree, &key, &bytes)?,
+                Written::Deleted(old) => part.set.hooks.after_delete(&part.tree, &key, &old)?,
+            }
+        }
+        return Ok(result);
+    }
+}
+
+// El contador se lee antes que las entradas: si un hijo cambia entre medias,
+// el intento siguiente ve otro contador y los vuelve a pedir
+fn look_up(conn: &Connection, missing: Vec<Children>, lookups: &mut Lookups) -> Result<(), OrmError> {
+    for (child, foreign_key, parent_key) in missing {
+        // Si el índice se está construyendo se espera a que termine
+        drop(conn.registry.schema(&child).index_builds.lock().unwrap());
+        let tree = conn.db.open_tree(Index::tree_name(&child, &foreign_key))?;
+        let generation = tree.get(Index::generation_key(&parent_key))?;
+        let prefix = Index::prefix(&parent_key);
+        let keys = tree
+            .scan_prefix(&prefix)
+            .keys()
+            .map(|entry| entry.map(|entry| IVec::from(&entry[prefix.len()..])))
+            .collect::<Result<_, _>>()?;
+        lookups.insert((child, foreign_key, parent_key), (generation, keys));
+    }
+    Ok(())
+}
+
+// Conjuntos de árboles que pueden participar en ORM::transaction. El closure
+// recibe un TypedTx por árbol con la misma forma que la entrada: una tupla
+// `(&users, &ledger)` da `(TypedTx, TypedTx)` y un array `[&a, &b]` da
+// `[TypedTx; 2]`.
+pub trait TransactionTrees<'a> {
+    type View;
+
+    fn trees(&self) -> Vec<&'a Tree>;
+
+    fn view(txs: Vec<TypedTx<'a>>) -> Self::View;
+}
+
+macro_rules! tuple_trees {
+    ($($name:ident),+) => {
+        impl<'a> TransactionTrees<'a> for ($(tuple_trees!(@tree $name),)+) {
+            type View = ($(tuple_trees!(@tx $name),)+);
+
+            fn trees(&self) -> Vec<&'a Tree> {
+                let ($($name,)+) = *self;
+                vec![$($name),+]
+            }
+
+            fn view(txs: Vec<TypedTx<'a>>) -> Self::View {
+                let mut txs = txs.into_iter();
+                ($(tuple_trees!(@next txs $name),)+)
+            }
+        }
+    };
+    (@tree $name:ident) => { &'a Tree };
+    (@tx $name:ident) => { TypedTx<'a> };
+    (@next $txs:ident $name:ident) => { $txs.next().expect("one TypedTx per tree") };
+}
+
+tuple_trees!(a, b);
+tuple_trees!(a, b, c);
+tuple_trees!(a, b, c, d);
+tuple_trees!(a, b, c, d, e);
+
+impl<'a, const N: usize> TransactionTrees<'a> for [&'a Tree; N] {
+    type View = [TypedTx<'a>; N];
+
+    fn trees(&self) -> Vec<&'a Tree> {
+        self.to_vec()
+    }
+
+    fn view(txs: Vec<TypedTx<'a>>) -> Self::View {
+        txs.try_into().ok().expect("one TypedTx per tree")
+    }
+}
+
+impl<'a, const N: usize> TransactionTrees<'a> for &[&'a Tree; N] {
+    type View = [TypedTx<'a>; N];
+
+    fn trees(&self) -> Vec<&'a Tree> {
+        self.to_vec()
+    }
+
+    fn view(txs: Vec<TypedTx<'a>>) -> Self::View {
+        txs.try_into().ok().expect("one TypedTx per tree")
+    }
+}
+
+impl<'a> TransactionTrees<'a> for &[&'a Tree] {
+    type View = Vec<TypedTx<'a>>;
+
+    fn trees(&self) -> Vec<&'a Tree> {
+        self.to_vec()
+    }
+
+    fn view(txs: Vec<TypedTx<'a>>) -> Self::View {
+        txs
+    }
+}
+
+impl ORM {
+    // Transacción atómica sobre varios árboles (y sus índices): o se
+    // confirman todas las escrituras o ninguna
+    pub fn transaction<'a, S, F, T>(&self, trees: S, f: F) -> Result<T, OrmError>
+    where
+        S: TransactionTrees<'a>,
+        F: Fn(&S::View) -> TxResult<T>,
+    {
+        let trees = trees.trees();
+        let mut names: Vec<String> = trees.iter().map(|t| t.name()).collect();
+        names.sort();
+        if let Some(w) = names.windows(2).find(|w| w[0] == w[1]) {
+            return Err(OrmError::DuplicateTree { tree: w[0].clone() });
+        }
+
+        run(&self.conn, &trees, |views| f(&S::view(views)))
+    }
+}
@@ -1,56 +1,134 @@
-use std::error::Error;
-
-use crate::{bincode_get_config,  Tree};
+use crate::{Codec, Compression, Key, OrmError, Tree};
 use serde::{Serialize, Deserialize};
-use bincode;
-use sled::{transaction::{ConflictableTransactionResult, TransactionResult, TransactionalTree}, IVec};
+use sled::IVec;
+use std::borrow::Cow;
 
 impl Tree {
-    pub fn insert<K, V>(&self, key: K, value: &V) -> Result<(), Box<dyn std::error::Error>>
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.tree.name()).into_owned()
+    }
+
+    // Serializa, comprime y cifra, en ese orden, según la configuración del
+    // árbol; `key` es la clave del registro, usada como dato asociado al cifrar
+    pub(crate) fn encode<V>(&self, key: &[u8], value: &V) -> Result<Vec<u8>, OrmError>
+    where
+        V: Serialize + ?Sized,
+    {
+        let compression = self.compression();
+        let encryption = self.schema.encryption();
+        self.codec()
+            .encode(value)
+            .and_then(|bytes| match compression {
+                Some(compression) => compression.compress(bytes),
+                None => Ok(bytes),
+            })
+            .and_then(|bytes| match encryption {
+                Some(encryption) => encryption.encrypt(key, &bytes),
+                None => Ok(bytes),
+            })
+            .map_err(|source| OrmError::Encode {
+                tree: self.name(),
+                type_name: std::any::type_name::<V>(),
+                source,
+            })
+    }
+
+    pub(crate) fn decode<V>(&self, key: &[u8], bytes: &[u8]) -> Result<V, OrmError>
+    where
+        V: for<'de> Deserialize<'de>,
+    {
+        let bytes = self.unseal::<V>(key, bytes)?;
+        self.codec().decode(&bytes).map_err(|source| OrmError::Decode {
+            tree: self.name(),
+            key: key.to_vec(),
+            type_name: std::any::type_name::<V>(),
+            source,
+        })
+    }
+
+    // Descifra y descomprime lo guardado: devuelve los bytes tal como los
+    // produjo el codec. `V` sólo se usa para el mensaje de error
+    pub(crate) fn unseal<'a, V: ?Sized>(&self, key: &[u8], bytes: &'a [u8]) -> Result<Cow<'a, [u8]>, OrmError> {
+        let bytes = match self.schema.encryption() {
+            Some(encryption) => Cow::Owned(encryption.decrypt(key, bytes).ok_or_else(|| OrmError::Decrypt {
+                tree: self.name(),
+                key: key.to_vec(),
+            })?),
+            None => Cow::Borrowed(bytes),
+        };
+
+        // Los árboles sin compresión guardan el valor tal cual, sin cabecera
+        match self.compression() {
+            Some(_) => Compression::decompress(&bytes)
+                .map(|bytes| Cow::Owned(bytes.into_owned()))
+                .map_err(|source| OrmError::Decode {
+                    tree: self.name(),
+                    key: key.to_vec(),
+                    type_name: std::any::type_name::<V>(),
+                    source,
+                }),
+            None => Ok(bytes),
+        }
+    }
+
+    pub(crate) fn decode_key<K: Key>(&self, key: &[u8]) -> Result<K, OrmError> {
+        K::from_key_bytes(key).ok_or_else(|| OrmError::InvalidKey {
+            tree: self.name(),
+            key: key.to_vec(),
+            type_name: std::any::type_name::<K>(),
+        })
+    }
+
+    // Punto único de escritura: si el árbol tiene índices, caducidades, hooks
+    // o referencias, el registro y todo lo que depende de él se escribe en la
+    // misma transacción (ver TypedTx::write)
+    pub(crate) fn write(&self, key: &[u8], value: Option<&[u8]>) -> Result<Option<IVec>, OrmError> {
+        {
+            let _writing = self.conn.registry.writing();
+            if !self.schema.needs_transaction() {
+                return Ok(match value {
+                    Some(bytes) => self.tree.insert(key, bytes)?,
+                    None => self.tree.remove(key)?,
+                });
+            }
+        }
+
+        self.transaction(|tx| tx.write(key, value))
+    }
+
+    pub fn insert<K, V>(&self, key: K, value: &V) -> Result<(), OrmError>
     where
         K: AsRef<[u8]>,
         V: Serialize,
     {
-        // Usar la función correcta de bincode::serde
-        let serialized = bincode::serde::encode_to_vec(value, bincode_get_config())?;
-        self.tree.insert(key, IVec::from(serialized))?;
+        let serialized = self.encode(key.as_ref(), value)?;
+        self.write(key.as_ref(), Some(&serialized))?;
         Ok(())
     }
 
-    pub fn get<K, V>(&self, key: K) -> Result<Option<V>, Box<dyn std::error::Error>>
+    pub fn get<K, V>(&self, key: K) -> Result<Option<V>, OrmError>
     where
         K: AsRef<[u8]>,
         V: for<'de> Deserialize<'de>,
     {
-        if let Some(ivec) = self.tree.get(key)? {
-            // decode_from_slice devuelve (T, usize), extraer solo el valor
-            let (value, _) = bincode::serde::decode_from_slice(&ivec, bincode_get_config())?;
-            Ok(Some(value))
-        } else {
-            Ok(None)
+        match self.tree.get(key.as_ref())? {
+            Some(_) if self.is_expired(key.as_ref())? => Ok(None),
+            Some(ivec) => Ok(Some(self.decode(key.as_ref(), &ivec)?)),
+            None => Ok(None),
         }
     }
 
-    pub fn find<F, V>(&self, predicate: F) -> Result<Vec<V>, Box<dyn std::error::Error>>
+    pub fn find<F, V>(&self, predicate: F) -> Result<Vec<V>, OrmError>
     where
         V: for<'de> Deserialize<'de>,
         F: Fn(&V) -> bool,
     {
-        let mut results = Vec::new();
-        
-        for item in self.tree.iter() {
-            let (_, value) = item?;
-            let (deserialized, _) = bincode::serde::decode_from_slice(&value, bincode_get_config())?;
-            
-            if predicate(&deserialized) {
-                results.push(deserialized);
-            }
-        }
-        
-        Ok(results)
+        self.filter_iter(predicate)
+            .map(|item| item.map(|(_, value)| value))
+            .collect()
     }
 
-    pub fn update<K, V>(&self, key: K, value: &V) -> Result<(), Box<dyn std::error::Error>>
+    pub fn update<K, V>(&self, key: K, value: &V) -> Result<(), OrmError>
     where
         K: AsRef<[u8]>,
         V: Serialize,
@@ -59,37 +137,15 @@ impl Tree {
         self.insert(key, value)
     }
 
-    pub fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Box<dyn std::error::Error>> {
-        self.tree.remove(key)?;
+    pub fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), OrmError> {
+        self.write(key.as_ref(), None)?;
         Ok(())
     }
 
-    pub fn all<V>(&self) -> Result<Vec<V>, Box<dyn Error>>
+    pub fn all<V>(&self) -> Result<Vec<V>, OrmError>
     where
         V: for<'de> Deserialize<'de>,
     {
-        let mut results = Vec::new();
-        
-        for item in self.tree.iter() {
-            let (_, value) = item?;
-            let (deserialized, _) = bincode::serde::decode_from_slice(&value, bincode_get_config())?;
-            results.push(deserialized);
-        }
-        
-        Ok(results)
-    }
-
-    pub fn transaction<F, T, E>(
-        &self,
-        f: F
-    ) -> TransactionResult<T, E>
-    where
-        F: Fn(&TransactionalTree) -> ConflictableTransactionResult<T, E>,
-        E: From<Box<dyn Error>> + From<String>,
-    {
-        self.tree.transaction(f)
+        self.iter().map(|item| item.map(|(_, value)| value)).collect()
     }
-
-    
 }
-
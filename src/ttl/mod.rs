@@ -0,0 +1,241 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ops::Bound;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use sled::{
+    transaction::{ConflictableTransactionError, ConflictableTransactionResult, TransactionalTree},
+    IVec,
+};
+
+use crate::{OrmError, Tree};
+
+const PURGE_BATCH: usize = 256;
+
+// Árboles compañeros de un árbol con registros que caducan:
+// `__ttl:<tree>:by_key` guarda clave -> instante de caducidad y
+// `__ttl:<tree>:by_time` guarda `instante ++ clave`, ordenado por caducidad
+// para que purgar sólo tenga que recorrer el principio.
+pub(crate) struct Ttl {
+    pub(crate) by_key: sled::Tree,
+    pub(crate) by_time: sled::Tree,
+}
+
+impl Ttl {
+    pub(crate) fn tree_names(owner: &str) -> [String; 2] {
+        [format!("__ttl:{}:by_key", owner), format!("__ttl:{}:by_time", owner)]
+    }
+
+    fn entry(expires_at: u64, key: &[u8]) -> Vec<u8> {
+        let mut entry = expires_at.to_be_bytes().to_vec();
+        entry.extend_from_slice(key);
+        entry
+    }
+}
+
+// Milisegundos desde UNIX_EPOCH
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+pub(crate) fn read_expiry(bytes: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+// Quita la caducidad de `key` dentro de una transacción; devuelve la que tenía
+pub(crate) fn clear(
+    by_key: &TransactionalTree,
+    by_time: &TransactionalTree,
+    key: &[u8],
+) -> ConflictableTransactionResult<Option<u64>, OrmError> {
+    let old = by_key.remove(key)?.as_deref().and_then(read_expiry);
+    if let Some(old) = old {
+        by_time.remove(Ttl::entry(old, key))?;
+    }
+    Ok(old)
+}
+
+pub(crate) fn set(
+    by_key: &TransactionalTree,
+    by_time: &TransactionalTree,
+    key: &[u8],
+    expires_at: u64,
+) -> ConflictableTransactionResult<(), OrmError> {
+    clear(by_key, by_time, key)?;
+    by_key.insert(key, &expires_at.to_be_bytes()[..])?;
+    by_time.insert(Ttl::entry(expires_at, key), IVec::default())?;
+    Ok(())
+}
+
+pub(crate) fn expired(expiry: Option<IVec>) -> bool {
+    expiry.as_deref().and_then(read_expiry).is_some_and(|at| at <= now())
+}
+
+impl Tree {
+    // Abre (creándolos si hace falta) los árboles de caducidad y los registra
+    // en el esquema, así todas las escrituras pasan a mantenerlos. Se cambia
+    // con las escrituras paradas para que ninguna en curso use el camino
+    // rápido y deje atrás una caducidad vieja
+    pub(crate) fn enable_ttl(&self) -> Result<(), OrmError> {
+        if self.schema.ttl().is_some() {
+            return Ok(());
+        }
+        let [by_key, by_time] = Ttl::tree_names(&self.name());
+        let ttl = Ttl {
+            by_key: self.conn.db.open_tree(by_key)?,
+            by_time: self.conn.db.open_tree(by_time)?,
+        };
+        let _writes = self.conn.registry.changing_indexes();
+        self.schema.set_ttl(ttl);
+        Ok(())
+    }
+
+    // Al abrir un árbol que ya tenía registros con caducidad
+    pub(crate) fn load_ttl(&self) -> Result<(), OrmError> {
+        if self.schema.ttl().is_some() {
+            return Ok(());
+        }
+        let [by_key, _] = Ttl::tree_names(&self.name());
+        if self.conn.db.tree_names().iter().any(|name| name == by_key.as_bytes()) {
+            self.enable_ttl()?;
+        }
+        Ok(())
+    }
+
+    // Inserta un valor que se trata como inexistente pasado `ttl`. Un insert
+    // normal sobre la misma clave le quita la caducidad.
+    pub fn insert_with_ttl<K, V>(&self, key: K, value: &V, ttl: Duration) -> Result<(), OrmError>
+    where
+        K: AsRef<[u8]>,
+        V: Serialize,
+    {
+        self.enable_ttl()?;
+        let bytes = self.encode(key.as_ref(), value)?;
+        let expires_at = now().saturating_add(ttl.as_millis() as u64);
+        self.transaction(|tx| {
+            tx.write(key.as_ref(), Some(&bytes))?;
+            tx.set_expiry(key.as_ref(), expires_at)
+        })
+    }
+
+    // Tiempo que le queda a un registro; None si no caduca o no existe
+    pub fn ttl<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Duration>, OrmError> {
+        let Some(ttl) = self.schema.ttl() else {
+            return Ok(None);
+        };
+        let expires_at = ttl.by_key.get(key)?.as_deref().and_then(read_expiry);
+        Ok(expires_at.map(|at| Duration::from_millis(at.saturating_sub(now()))))
+    }
+
+    pub(crate) fn is_expired(&self, key: &[u8]) -> Result<bool, OrmError> {
+        match self.schema.ttl() {
+            Some(ttl) => Ok(expired(ttl.by_key.get(key)?)),
+            None => Ok(false),
+        }
+    }
+
+    // Borra los registros caducados por lotes; devuelve cuántos se borraron.
+    // Los borrados pasan por los hooks y las referencias del árbol: si uno se
+    // veta (before_delete, OnDelete::Restrict...) ese registro sigue caducado,
+    // sin verse, y se vuelve a intentar en la siguiente purga.
+    pub fn purge_expired(&self) -> Result<usize, OrmError> {
+        let Some(ttl) = self.schema.ttl() else {
+            return Ok(0);
+        };
+        let limit = Ttl::entry(now() + 1, &[]);
+        let mut start = Bound::Unbounded;
+        let mut purged = 0;
+        loop {
+            let due = ttl
+                .by_time
+                .range::<Vec<u8>, _>((start, Bound::Excluded(limit.clone())))
+                .take(PURGE_BATCH)
+                .collect::<Result<Vec<_>, _>>()?;
+            let Some((last, _)) = due.last() else {
+                return Ok(purged);
+            };
+
+            let mut vetoed = HashSet::new();
+            let failed = RefCell::new(None);
+            purged += loop {
+                let result = self.transaction(|tx| {
+                    failed.replace(None);
+                    let mut deleted = 0;
+                    for (entry, _) in &due {
+                        let (expires_at, key) = entry.split_at(8);
+                        if vetoed.contains(key) {
+                            continue;
+                        }
+                        // Sólo si nadie renovó o quitó la caducidad entretanto
+                        if tx.expiry(key)? != read_expiry(expires_at) {
+                            tx.remove_stale_expiry(entry)?;
+                            continue;
+                        }
+                        if let Err(e) = tx.write(key, None) {
+                            if let ConflictableTransactionError::Abort(_) = e {
+                                failed.replace(Some(key.to_vec()));
+                            }
+                            return Err(e);
+                        }
+                        deleted += 1;
+                    }
+                    Ok(deleted)
+                });
+                match (result, failed.take()) {
+                    (Ok(deleted), _) => break deleted,
+                    (Err(_), Some(key)) => {
+                        vetoed.insert(key);
+                    }
+                    (Err(e), None) => return Err(e),
+                }
+            };
+            start = Bound::Excluded(last.to_vec());
+        }
+    }
+
+    // Lanza un hilo que llama a purge_expired cada `interval`. El hilo para
+    // con Sweeper::stop, al soltar el Sweeper o al primer error.
+    pub fn spawn_sweeper(&self, interval: Duration) -> Sweeper {
+        let (stop, signal) = mpsc::channel::<()>();
+        let tree = self.clone();
+        let handle = std::thread::spawn(move || -> Result<usize, OrmError> {
+            let mut purged = 0;
+            loop {
+                match signal.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => purged += tree.purge_expired()?,
+                    _ => return Ok(purged),
+                }
+            }
+        });
+        Sweeper { stop: Some(stop), handle: Some(handle) }
+    }
+}
+
+pub struct Sweeper {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<Result<usize, OrmError>>>,
+}
+
+impl Sweeper {
+    // Detiene el hilo y devuelve cuántos registros purgó en total
+    pub fn stop(mut self) -> Result<usize, OrmError> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<usize, OrmError> {
+        drop(self.stop.take());
+        match self.handle.take() {
+            Some(handle) => handle.join().expect("ttl sweeper panicked"),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
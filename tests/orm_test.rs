@@ -1,10 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
     use serde::{Serialize, Deserialize};
     use tempfile::tempdir;
-    use std::path::Path;
-    use sled_orm::Connection;
+    use sled_orm::{Connection, Cursor, Model, OrmError, Versioned};
+    use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult};
     use std::time::Instant;
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
@@ -12,6 +11,21 @@ mod tests {
     // Contador global para tests
     static TEST_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
+    // Reabre una base dentro del mismo proceso: sled suelta el bloqueo del
+    // fichero en segundo plano tras cerrar la anterior, así que se reintenta
+    fn reopen(path: &std::path::Path) -> Result<Connection, OrmError> {
+        let mut attempts = 0;
+        loop {
+            match Connection::new(path.to_str().unwrap()) {
+                Err(OrmError::Sled(sled::Error::Io(e))) if e.to_string().contains("could not acquire lock") && attempts < 200 => {
+                    attempts += 1;
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                result => return result,
+            }
+        }
+    }
+
     // Modelo de prueba
     #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
     struct TestUser {
@@ -57,6 +71,7 @@ mod tests {
 
     // Test de ORM y árboles (corregido)
     #[test]
+    #[allow(unused_variables)]
     fn test_orm_and_trees() -> Result<(), Box<dyn std::error::Error>> {
         let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
         println!("🧪 Starting test_orm_and_trees #{}", test_id);
@@ -313,6 +328,7 @@ mod tests {
 
     // Test de serialización/deserialización corregido
     #[test]
+    #[allow(clippy::approx_constant, clippy::bool_assert_comparison)]
     fn test_serialization() -> Result<(), Box<dyn std::error::Error>> {
         let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
         println!("🧪 Starting test_serialization #{}", test_id);
@@ -438,13 +454,47 @@ mod tests {
         
         println!("💾 Testing transaction support...");
         
-        // Aquí irían las pruebas de transacciones cuando las implementes
-        // Por ahora verificamos que el árbol funciona normalmente
         tree.insert("test_key", &"test_value")?;
         let value: Option<String> = tree.get("test_key")?;
         assert_eq!(value, Some("test_value".to_string()));
-        
-        println!("✅ Basic functionality works (transactions to be implemented)");
+
+        // Transacción tipada: los valores se (de)codifican dentro del closure
+        tree.insert("balance", &150_i32)?;
+        let charge = |amount: i32| tree.transaction(|tx| {
+            let current_value: Option<i32> = tx.get(b"balance")?;
+
+            if let Some(balance) = current_value {
+                if balance < amount {
+                    return tx.abort("You dont have money");
+                }
+                tx.insert(b"balance", &(balance - amount))?;
+            }
+
+            Ok(())
+        });
+
+        charge(100)?;
+        assert_eq!(tree.get::<_, i32>("balance")?, Some(50));
+
+        let rejected = charge(100);
+        assert!(matches!(rejected, Err(OrmError::Aborted(ref reason)) if reason == "You dont have money"));
+        assert_eq!(tree.get::<_, i32>("balance")?, Some(50));
+
+        // Los índices se mantienen también dentro de la transacción
+        let users_tree = orm.tree("users")?;
+        users_tree.create_index("age", |u: &TestUser| u.age)?;
+        users_tree.transaction(|tx| {
+            tx.insert("user_1", &TestUser::new("user_1", "Alice", "alice@example.com", 25))?;
+            tx.insert("user_2", &TestUser::new("user_2", "Bob", "bob@example.com", 25))?;
+            tx.remove("user_2")
+        })?;
+        assert_eq!(users_tree.find_keys_by_index("age", &25_u32)?.len(), 1);
+
+        // Un error de decodificación aborta con el error tipado
+        let decode = tree.transaction(|tx| tx.get::<_, TestUser>("balance"));
+        assert!(matches!(decode, Err(OrmError::Decode { .. })));
+
+        println!("✅ Typed transactions work");
         Ok(())
     }
 
@@ -485,4 +535,2284 @@ mod tests {
         println!("✅ All stress test operations completed in {:?}", total_start.elapsed());
         Ok(())
     }
+
+    // Test de errores tipados
+    #[test]
+    fn test_typed_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_typed_errors #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let tree = orm.tree("errors")?;
+
+        // Un bool no se puede decodificar como TestUser
+        tree.insert("flag", &true)?;
+        match tree.get::<_, TestUser>("flag") {
+            Err(OrmError::Decode { tree, key, type_name, .. }) => {
+                assert_eq!(tree, "errors");
+                assert_eq!(key, b"flag".to_vec());
+                assert!(type_name.ends_with("TestUser"));
+            }
+            other => panic!("expected decode error, got {:?}", other),
+        }
+
+        // Los abortos de la transacción llegan como OrmError::Aborted
+        let result = tree.transaction(|_tx| -> ConflictableTransactionResult<(), OrmError> {
+            Err(ConflictableTransactionError::Abort("You dont have money".into()))
+        });
+        assert!(matches!(result, Err(OrmError::Aborted(ref reason)) if reason == "You dont have money"));
+
+        // OrmError debe poder cruzar hilos
+        let handle = std::thread::spawn(move || -> Result<(), OrmError> {
+            tree.insert("from_thread", &1_u32)?;
+            Err(OrmError::NotFound { tree: tree.name(), key: b"missing".to_vec() })
+        });
+        let err = handle.join().unwrap().unwrap_err();
+        assert!(err.is_not_found());
+        let boxed: Box<dyn std::error::Error + Send + Sync> = err.into();
+        println!("✅ Typed error crossed threads: {}", boxed);
+
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "users")]
+    struct Member {
+        #[primary_key]
+        id: u64,
+        name: String,
+    }
+
+    // Test del trait Model con derive
+    #[test]
+    fn test_model_derive() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_model_derive #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        assert_eq!(Member::TREE, "users");
+
+        let mut member = Member { id: 7, name: "Reiner Brawn".to_string() };
+        member.save(&orm)?;
+
+        let loaded = Member::load(&orm, &7)?.expect("Member should exist");
+        assert_eq!(loaded, member);
+
+        // reload descarta los cambios locales no guardados
+        member.name = "Bertholdt".to_string();
+        member.reload(&orm)?;
+        assert_eq!(member.name, "Reiner Brawn");
+
+        member.delete(&orm)?;
+        assert!(Member::load(&orm, &7)?.is_none());
+        assert!(member.reload(&orm).unwrap_err().is_not_found());
+
+        println!("✅ Model derive works");
+        Ok(())
+    }
+
+    // Test de colecciones tipadas
+    #[test]
+    fn test_typed_collection() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_typed_collection #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let users = orm.collection::<String, TestUser>("users")?;
+
+        let alice = TestUser::new("user_1", "Alice", "alice@example.com", 25);
+        let bob = TestUser::new("user_2", "Bob", "bob@example.com", 30);
+        users.insert(&alice.id, &alice)?;
+        users.insert(&bob.id, &bob)?;
+
+        assert_eq!(users.get(&alice.id)?, Some(alice.clone()));
+        assert_eq!(users.len(), 2);
+
+        let older = users.find(|u| u.age >= 30)?;
+        assert_eq!(older, vec![(bob.id.clone(), bob.clone())]);
+
+        users.delete(&bob.id)?;
+        assert!(!users.contains(&bob.id)?);
+        assert_eq!(users.all()?, vec![(alice.id.clone(), alice)]);
+
+        // Claves numéricas en big endian conservan el orden
+        let scores = orm.collection::<i64, u32>("scores")?;
+        scores.insert(&10, &1)?;
+        scores.insert(&-5, &2)?;
+        scores.insert(&3, &3)?;
+        let keys: Vec<i64> = scores.all()?.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![-5, 3, 10]);
+
+        println!("✅ Typed collection works");
+        Ok(())
+    }
+
+    // Test de índices secundarios
+    #[test]
+    fn test_secondary_indexes() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_secondary_indexes #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let users_tree = orm.tree("users")?;
+
+        // Datos guardados antes de declarar el índice
+        let alice = TestUser::new("user_1", "Alice", "alice@example.com", 25);
+        users_tree.insert(&alice.id, &alice)?;
+
+        users_tree.create_index("email", |u: &TestUser| u.email.clone())?;
+        users_tree.create_index("age", |u: &TestUser| u.age)?;
+
+        let found: Vec<TestUser> = users_tree.find_by_index("email", &"alice@example.com".to_string())?;
+        assert_eq!(found, vec![alice.clone()]);
+
+        // Otra instancia del mismo árbol comparte los índices
+        let same_tree = orm.tree("users")?;
+        let bob = TestUser::new("user_2", "Bob", "bob@example.com", 25);
+        same_tree.insert(&bob.id, &bob)?;
+
+        let same_age: Vec<TestUser> = users_tree.find_by_index("age", &25_u32)?;
+        assert_eq!(same_age.len(), 2);
+
+        // El update mueve la entrada del índice
+        let mut older_bob = bob.clone();
+        older_bob.age = 31;
+        users_tree.update(&bob.id, &older_bob)?;
+        assert_eq!(users_tree.find_by_index::<_, TestUser>("age", &25_u32)?, vec![alice.clone()]);
+        assert_eq!(users_tree.find_by_index::<_, TestUser>("age", &31_u32)?, vec![older_bob]);
+
+        users_tree.delete(&alice.id)?;
+        assert!(users_tree.find_by_index::<_, TestUser>("email", &alice.email)?.is_empty());
+
+        users_tree.rebuild_index("age")?;
+        assert_eq!(users_tree.find_keys_by_index("age", &31_u32)?.len(), 1);
+
+        assert!(matches!(
+            users_tree.find_keys_by_index("name", &alice.name),
+            Err(OrmError::UnknownIndex { .. })
+        ));
+
+        println!("✅ Secondary indexes work");
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "accounts")]
+    struct Account {
+        #[primary_key]
+        id: String,
+        #[unique]
+        email: String,
+        #[index]
+        guild_id: u64,
+    }
+
+    // Test de restricciones únicas
+    #[test]
+    fn test_unique_constraints() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_unique_constraints #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        let first = Account { id: "a1".to_string(), email: "eren@example.com".to_string(), guild_id: 1 };
+        let second = Account { id: "a2".to_string(), email: "eren@example.com".to_string(), guild_id: 1 };
+        first.save(&orm)?;
+
+        match second.save(&orm) {
+            Err(OrmError::UniqueViolation { field, existing_key, .. }) => {
+                assert_eq!(field, "email");
+                assert_eq!(existing_key, b"a1".to_vec());
+            }
+            other => panic!("expected unique violation, got {:?}", other),
+        }
+
+        // Un registro puede conservar su propio valor al actualizarse
+        let mut moved = first.clone();
+        moved.guild_id = 2;
+        moved.save(&orm)?;
+
+        // Al liberar el email, otro registro puede usarlo
+        moved.delete(&orm)?;
+        second.save(&orm)?;
+        let tree = Account::tree(&orm)?;
+        assert_eq!(tree.find_by_index::<_, Account>("guild_id", &1_u64)?, vec![second]);
+
+        // Inserciones concurrentes con el mismo email: sólo una puede ganar
+        let users_tree = Arc::new(orm.tree("users")?);
+        users_tree.create_unique_index("email", |u: &TestUser| u.email.clone())?;
+        let handles: Vec<_> = (0..10)
+            .map(|i| {
+                let tree = users_tree.clone();
+                std::thread::spawn(move || {
+                    let user = TestUser::new(&format!("user_{}", i), "Mikasa", "mikasa@example.com", 19);
+                    tree.insert(&user.id, &user)
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results.iter().filter_map(|r| r.as_ref().err()).all(|e| e.is_conflict()));
+        assert_eq!(users_tree.tree.len(), 1);
+
+        println!("✅ Unique constraints enforced");
+        Ok(())
+    }
+
+    // Test del query builder
+    #[test]
+    fn test_query_builder() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_query_builder #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let users_tree = orm.tree("users")?;
+
+        for i in 0..50_u32 {
+            let user = TestUser::new(&format!("user_{:02}", i), &format!("User {}", i), "user@example.com", (i * 7) % 50);
+            users_tree.insert(&user.id, &user)?;
+        }
+
+        // Top-N ordenado con skip
+        let page = users_tree.query::<TestUser>()
+            .filter(|u| u.age >= 10)
+            .order_by(|u| u.age)
+            .skip(5)
+            .limit(3)
+            .fetch()?;
+        let ages: Vec<u32> = page.iter().map(|u| u.age).collect();
+        assert_eq!(ages, vec![15, 16, 17]);
+
+        // Orden descendente sin límite
+        let oldest = users_tree.query::<TestUser>().order_by_desc(|u| u.age).fetch()?;
+        assert_eq!(oldest.len(), 50);
+        assert_eq!(oldest[0].age, 49);
+
+        // Sin orden se respetan las claves
+        let first_keys = users_tree.query::<TestUser>().skip(2).limit(2).fetch()?;
+        assert_eq!(first_keys[0].id, "user_02");
+        assert_eq!(first_keys[1].id, "user_03");
+
+        let youngest = users_tree.query::<TestUser>().order_by(|u| u.age).first()?;
+        assert_eq!(youngest.map(|u| u.age), Some(0));
+
+        assert_eq!(users_tree.query::<TestUser>().filter(|u| u.age < 10).count()?, 10);
+        assert_eq!(users_tree.query::<TestUser>().filter(|u| u.age < 10).limit(4).count()?, 4);
+        assert!(users_tree.query::<TestUser>().filter(|u| u.age == 49).exists()?);
+        assert!(!users_tree.query::<TestUser>().filter(|u| u.age > 100).exists()?);
+
+        println!("✅ Query builder works");
+        Ok(())
+    }
+
+    // Test de iteradores perezosos
+    #[test]
+    fn test_streaming_iterators() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_streaming_iterators #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let tree = orm.tree("numbers")?;
+
+        for i in 0..1000_u32 {
+            tree.insert(format!("key_{:04}", i), &i)?;
+        }
+
+        // Corte temprano: sólo se decodifican los registros consumidos
+        let first_three: Vec<u32> = tree.iter::<u32>()
+            .take(3)
+            .map(|item| item.map(|(_, v)| v))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(first_three, vec![0, 1, 2]);
+
+        let (last_key, last_value) = tree.iter::<u32>().next_back().unwrap()?;
+        assert_eq!(&last_key[..], b"key_0999");
+        assert_eq!(last_value, 999);
+
+        let even_sum: u64 = tree.filter_iter(|v: &u32| v.is_multiple_of(2))
+            .map(|item| item.map(|(_, v)| v as u64))
+            .sum::<Result<u64, _>>()?;
+        assert_eq!(even_sum, (0..1000_u64).filter(|v| v.is_multiple_of(2)).sum::<u64>());
+
+        // Un valor con otro tipo llega como error sin detener la iteración
+        tree.insert("key_0500", &true)?;
+        let errors = tree.iter::<u32>().filter(|item| item.is_err()).count();
+        assert_eq!(errors, 1);
+
+        let collection = orm.collection::<String, u32>("numbers")?;
+        let keys: Vec<String> = collection.iter().take(2).map(|item| item.map(|(k, _)| k)).collect::<Result<_, _>>()?;
+        assert_eq!(keys, vec!["key_0000".to_string(), "key_0001".to_string()]);
+
+        println!("✅ Streaming iterators work");
+        Ok(())
+    }
+
+    // Test de rangos y prefijos
+    #[test]
+    fn test_range_and_prefix_scans() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_range_and_prefix_scans #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        // Eventos indexados por timestamp
+        let events = orm.tree("events")?;
+        for ts in [100_u64, 200, 300, 400, 500] {
+            events.insert(ts.to_be_bytes(), &format!("event_{}", ts))?;
+        }
+        let between: Vec<(u64, String)> = events.range(200_u64..400).collect::<Result<_, _>>()?;
+        assert_eq!(between, vec![(200, "event_200".to_string()), (300, "event_300".to_string())]);
+
+        let latest: Vec<u64> = events.range_rev::<u64, String, _>(300_u64..)
+            .map(|item| item.map(|(ts, _)| ts))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(latest, vec![500, 400, 300]);
+
+        // Claves de un guild concreto
+        let settings = orm.tree("settings")?;
+        settings.insert("guild:1234:prefix", &"!")?;
+        settings.insert("guild:1234:lang", &"es")?;
+        settings.insert("guild:5678:lang", &"en")?;
+        let guild: Vec<String> = settings.prefix::<String, _>("guild:1234:")
+            .map(|item| item.map(|(_, v)| v))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(guild, vec!["es".to_string(), "!".to_string()]);
+
+        let last = settings.prefix_rev::<String, _>("guild:").next().unwrap()?;
+        assert_eq!(&last.0[..], b"guild:5678:lang");
+
+        let typed = orm.collection::<u64, String>("events")?;
+        assert_eq!(typed.range(..=100).count(), 1);
+
+        println!("✅ Range and prefix scans work");
+        Ok(())
+    }
+
+    // Test de paginación con cursores
+    #[test]
+    fn test_cursor_pagination() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_cursor_pagination #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let warnings = orm.collection::<u32, String>("warnings")?;
+
+        for i in 0..10_u32 {
+            warnings.insert(&(i * 10), &format!("warning {}", i))?;
+        }
+
+        let first = warnings.page(None, 4)?;
+        let keys: Vec<u32> = first.items.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![0, 10, 20, 30]);
+        assert!(first.prev.is_none());
+
+        // El cursor viaja como texto (p. ej. en el custom_id de un botón)
+        let token = first.next.clone().expect("there is a second page").to_token();
+        let cursor = Cursor::from_token(&token)?;
+
+        // Cambios entre peticiones no desplazan la página
+        warnings.insert(&5, &"late warning".to_string())?;
+        warnings.delete(&40)?;
+
+        let second = warnings.page(Some(cursor), 4)?;
+        let keys: Vec<u32> = second.items.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![50, 60, 70, 80]);
+
+        let third = warnings.page(second.next.clone(), 4)?;
+        let keys: Vec<u32> = third.items.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![90]);
+        assert!(third.next.is_none());
+
+        // Hacia atrás
+        let back = warnings.page(second.prev.clone(), 4)?;
+        let keys: Vec<u32> = back.items.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![5, 10, 20, 30]);
+        assert!(back.next.is_some());
+        let start = warnings.page(back.prev.clone(), 4)?;
+        let keys: Vec<u32> = start.items.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![0]);
+        assert!(start.prev.is_none());
+
+        assert!(Cursor::from_token("zz").is_err());
+
+        // La versión sin tipo de clave
+        let tree = orm.tree("warnings")?;
+        assert_eq!(tree.page::<String>(None, 100)?.items.len(), 10);
+        assert!(matches!(tree.page::<String>(None, 0), Err(OrmError::InvalidPageSize)));
+
+        // Un tamaño de página enorme no desborda ni reserva memoria de golpe
+        assert_eq!(tree.page::<String>(None, usize::MAX)?.items.len(), 10);
+        let rest = tree.page::<String>(tree.page::<String>(None, 4)?.next, usize::MAX)?;
+        assert_eq!(rest.items.len(), 6);
+        assert!(rest.next.is_none());
+        assert_eq!(tree.page::<String>(rest.prev, usize::MAX)?.items.len(), 4);
+
+        println!("✅ Cursor pagination works");
+        Ok(())
+    }
+
+    // Test de transacciones sobre varios árboles
+    #[test]
+    fn test_multi_tree_transactions() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_multi_tree_transactions #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let users = orm.tree("coins")?;
+        let ledger = orm.tree("ledger")?;
+        ledger.create_index("user", |entry: &(String, i64)| entry.0.clone())?;
+
+        users.insert("alice", &100_i64)?;
+
+        let transfer = |amount: i64| orm.transaction((&users, &ledger), |(u, l)| {
+            let balance: i64 = u.get("alice")?.unwrap_or(0);
+            if balance < amount {
+                return u.abort("You dont have money");
+            }
+            u.insert("alice", &(balance - amount))?;
+            l.insert(format!("tx_{}", balance), &("alice".to_string(), -amount))?;
+            Ok(balance - amount)
+        });
+
+        assert_eq!(transfer(60)?, 40);
+        assert!(matches!(transfer(60), Err(OrmError::Aborted(_))));
+
+        // El aborto no deja escrituras a medias en ningún árbol
+        assert_eq!(users.get::<_, i64>("alice")?, Some(40));
+        assert_eq!(ledger.tree.len(), 1);
+        assert_eq!(ledger.find_keys_by_index("user", &"alice".to_string())?.len(), 1);
+
+        // También con arrays
+        orm.transaction([&users, &ledger], |[u, l]| {
+            u.remove("alice")?;
+            l.remove("tx_100")
+        })?;
+        assert!(users.get::<_, i64>("alice")?.is_none());
+        assert!(ledger.find_keys_by_index("user", &"alice".to_string())?.is_empty());
+
+        let same = orm.tree("coins")?;
+        match orm.transaction((&users, &same), |_| Ok(())) {
+            Err(OrmError::DuplicateTree { tree }) => assert_eq!(tree, "coins"),
+            other => panic!("expected duplicate tree error, got {:?}", other),
+        }
+
+        println!("✅ Multi-tree transactions work");
+        Ok(())
+    }
+
+    // Valor que nunca se puede serializar
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("cannot serialize this value"))
+        }
+    }
+
+    // Test de escrituras por lotes
+    #[test]
+    fn test_batch_writes() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_batch_writes #{}", test_id);
+        let total_start = Instant::now();
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let tree = orm.tree("performance")?;
+
+        let num_items = 5000;
+        let inserted = tree.insert_many((0..num_items).map(|i| (format!("key_{:05}", i), format!("value_{}", i))))?;
+        assert_eq!(inserted, num_items);
+        assert_eq!(tree.tree.len(), num_items);
+        println!("📦 Inserted {} items in one batch in {:?}", num_items, total_start.elapsed());
+
+        let deleted = tree.delete_many((0..100).map(|i| format!("key_{:05}", i)))?;
+        assert_eq!(deleted, 100);
+        assert_eq!(tree.tree.len(), num_items - 100);
+
+        let mut batch = tree.batch();
+        batch.insert("a", &1_u32).remove("key_00100").insert("b", &2_u32);
+        batch.apply()?;
+        assert_eq!(tree.get::<_, u32>("b")?, Some(2));
+        assert!(tree.get::<_, String>("key_00100")?.is_none());
+
+        // Un fallo de codificación rechaza el lote entero
+        let mut batch = tree.batch();
+        batch.insert("c", &3_u32).insert("bad", &Unserializable).remove("a");
+        match batch.apply() {
+            Err(OrmError::Batch { errors, .. }) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].index, 1);
+                assert_eq!(errors[0].key, b"bad".to_vec());
+                assert!(matches!(errors[0].error, OrmError::Encode { .. }));
+            }
+            other => panic!("expected batch error, got {:?}", other),
+        }
+        assert!(tree.get::<_, u32>("c")?.is_none());
+        assert_eq!(tree.get::<_, u32>("a")?, Some(1));
+
+        // Con índices el lote pasa por una transacción
+        let users_tree = orm.tree("users")?;
+        users_tree.create_index("age", |u: &TestUser| u.age)?;
+        let users: Vec<TestUser> = (0..20).map(|i| TestUser::new(&format!("user_{}", i), "User", "user@example.com", i % 2)).collect();
+        users_tree.insert_many(users.iter().map(|u| (u.id.as_str(), u)))?;
+        assert_eq!(users_tree.find_keys_by_index("age", &1_u32)?.len(), 10);
+
+        println!("✅ Batch writes completed in {:?}", total_start.elapsed());
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct GuildSettings {
+        prefix: String,
+        volume: u32,
+    }
+
+    // Test de compare-and-swap y bloqueo optimista
+    #[test]
+    fn test_compare_and_swap() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_compare_and_swap #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let tree = orm.tree("guild_settings")?;
+
+        let initial = GuildSettings { prefix: "!".to_string(), volume: 50 };
+        tree.compare_and_swap("guild_1", None, Some(&initial))?;
+
+        let louder = GuildSettings { volume: 80, ..initial.clone() };
+        tree.compare_and_swap("guild_1", Some(&initial), Some(&louder))?;
+
+        // El segundo shard todavía cree que el valor es `initial`
+        let other = GuildSettings { prefix: "?".to_string(), ..initial.clone() };
+        let err = tree.compare_and_swap("guild_1", Some(&initial), Some(&other)).unwrap_err();
+        assert!(err.is_conflict());
+        assert_eq!(err.current_value::<GuildSettings>(), Some(&louder));
+        assert_eq!(tree.get::<_, GuildSettings>("guild_1")?, Some(louder.clone()));
+
+        tree.compare_and_swap::<_, GuildSettings>("guild_1", Some(&louder), None)?;
+        assert!(tree.get::<_, GuildSettings>("guild_1")?.is_none());
+
+        // Bloqueo optimista con versiones
+        let versions = Arc::new(orm.tree("versioned")?);
+        assert_eq!(versions.update_versioned("guild_1", &Versioned::new(initial.clone()))?, 1);
+
+        let mut stale = versions.get_versioned::<_, GuildSettings>("guild_1")?.unwrap();
+        let mut fresh = stale.clone();
+        fresh.value.volume = 10;
+        assert_eq!(versions.update_versioned("guild_1", &fresh)?, 2);
+
+        stale.value.prefix = "$".to_string();
+        let err = versions.update_versioned("guild_1", &stale).unwrap_err();
+        let current = err.current_value::<Versioned<GuildSettings>>().unwrap();
+        assert_eq!(current.version, 2);
+        assert_eq!(current.value.volume, 10);
+
+        // Varios hilos reintentando nunca pierden una escritura
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let tree = versions.clone();
+                std::thread::spawn(move || -> Result<(), OrmError> {
+                    for _ in 0..25 {
+                        loop {
+                            let mut record = tree.get_versioned::<_, GuildSettings>("guild_1")?.unwrap();
+                            record.value.volume += 1;
+                            match tree.update_versioned("guild_1", &record) {
+                                Ok(_) => break,
+                                Err(e) if e.is_conflict() => continue,
+                                Err(e) => return Err(e),
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        let final_record = versions.get_versioned::<_, GuildSettings>("guild_1")?.unwrap();
+        assert_eq!(final_record.value.volume, 10 + 200);
+        assert_eq!(final_record.version, 2 + 200);
+
+        println!("✅ Compare-and-swap works");
+        Ok(())
+    }
+
+    // Test de lectura-modificación-escritura atómica con update_with
+    #[test]
+    fn test_update_with() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_update_with #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let xp = Arc::new(orm.tree("xp")?);
+
+        let (old, new) = xp.update_with("user_1", |current: Option<u64>| Some(current.unwrap_or(0) + 10))?;
+        assert_eq!((old, new), (None, Some(10)));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let xp = xp.clone();
+                std::thread::spawn(move || -> Result<(), OrmError> {
+                    for _ in 0..50 {
+                        xp.update_with("user_1", |current: Option<u64>| current.map(|v| v + 1))?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        assert_eq!(xp.get::<_, u64>("user_1")?, Some(410));
+
+        // Devolver None borra el registro
+        let (old, new) = xp.update_with("user_1", |_: Option<u64>| None)?;
+        assert_eq!((old, new), (Some(410), None));
+        assert!(xp.get::<_, u64>("user_1")?.is_none());
+
+        // Un valor que no se puede decodificar deja el registro intacto
+        xp.insert("broken", &true)?;
+        assert!(xp.update_with("broken", |v: Option<u64>| v).is_err());
+        assert_eq!(xp.get::<_, bool>("broken")?, Some(true));
+
+        // En árboles con índices también se mantienen los índices
+        let accounts = Account::tree(&orm)?;
+        let account = Account { id: "a1".to_string(), email: "a@x.com".to_string(), guild_id: 1 };
+        account.save(&orm)?;
+        let (old, new) = accounts.update_with("a1", |current: Option<Account>| {
+            current.map(|a| Account { guild_id: 2, ..a })
+        })?;
+        assert_eq!(old.unwrap().guild_id, 1);
+        assert_eq!(new.unwrap().guild_id, 2);
+        assert!(accounts.find_by_index::<_, Account>("guild_id", &1u64)?.is_empty());
+        assert_eq!(accounts.find_by_index::<_, Account>("guild_id", &2u64)?.len(), 1);
+
+        println!("✅ update_with works");
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct PlayerV0 {
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct PlayerV1 {
+        name: String,
+        xp: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct PlayerV2 {
+        name: String,
+        xp: u64,
+        level: u32,
+    }
+
+    // Test de migraciones de esquema
+    #[test]
+    fn test_schema_migrations() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_schema_migrations #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+
+        {
+            let conn = Connection::new(db_path.to_str().unwrap())?;
+            let orm = conn.get_orm();
+            let players = orm.tree("players")?;
+            for i in 0..600u32 {
+                players.insert(i.to_be_bytes(), &PlayerV0 { name: format!("player_{}", i) })?;
+            }
+            assert_eq!(orm.schema_version("players")?, 0);
+
+            // Simula un despliegue anterior que se cortó tras migrar las 10 primeras claves
+            for i in 0..10u32 {
+                players.insert(i.to_be_bytes(), &PlayerV1 { name: format!("player_{}", i), xp: 0 })?;
+            }
+            orm.tree("__meta")?.insert("cursor:players", &9u32.to_be_bytes().to_vec())?;
+            conn.db.flush()?;
+        }
+
+        let conn = reopen(&db_path)?;
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = reports.clone();
+        conn.migrations("players")
+            .step(0, |old: &[u8]| {
+                let (old, _): (PlayerV0, _) = bincode::serde::decode_from_slice(old, sled_orm::bincode_get_config())
+                    .map_err(|e| OrmError::Aborted(e.to_string()))?;
+                Ok(PlayerV1 { name: old.name, xp: 0 })
+            })
+            .map(1, |old: PlayerV1| PlayerV2 { name: old.name, xp: old.xp, level: 1 })
+            .on_progress(move |p| seen.lock().unwrap().push(p.clone()));
+
+        let orm = conn.get_orm();
+        let players = orm.collection::<u32, PlayerV2>("players")?;
+        assert_eq!(orm.schema_version("players")?, 2);
+        let all = players.all()?;
+        assert_eq!(all.len(), 600);
+        assert!(all.iter().enumerate().all(|(i, (k, p))| *k == i as u32 && p.name == format!("player_{}", i) && p.level == 1));
+
+        // Dos lotes por paso; el primer paso retoma desde las 10 claves ya migradas
+        let reports = reports.lock().unwrap();
+        let first: Vec<_> = reports.iter().filter(|p| p.from_version == 0).map(|p| p.migrated).collect();
+        let second: Vec<_> = reports.iter().filter(|p| p.from_version == 1).map(|p| p.migrated).collect();
+        assert_eq!(first, vec![522, 600]);
+        assert_eq!(second, vec![512, 600]);
+        assert!(reports.iter().all(|p| p.total == 600 && p.to_version == p.from_version + 1));
+
+        // Reabrir el árbol no vuelve a migrar nada
+        drop(reports);
+        orm.tree("players")?;
+        assert_eq!(orm.schema_version("players")?, 2);
+
+        // Un hueco en la cadena de pasos es un error
+        conn.migrations("broken").map(1, |old: PlayerV1| old);
+        let err = orm.tree("broken").err().unwrap();
+        assert!(matches!(err, OrmError::MissingMigration { version: 0, .. }));
+
+        println!("✅ Schema migrations work");
+        Ok(())
+    }
+
+    // Test de migraciones con índices, caducidades y escrituras concurrentes
+    #[test]
+    fn test_migrations_keep_indexes() -> Result<(), Box<dyn std::error::Error>> {
+        use std::time::Duration;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_migrations_keep_indexes #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+
+        {
+            let conn = Connection::new(db_path.to_str().unwrap())?;
+            let orm = conn.get_orm();
+            let heroes = orm.tree("heroes")?;
+            heroes.create_index("by_name", |p: &PlayerV0| p.name.clone())?;
+            heroes.create_index("by_first", |p: &PlayerV0| p.name[..1].to_string())?;
+            for i in 0..20u32 {
+                heroes.insert(i.to_be_bytes(), &PlayerV0 { name: format!("hero_{}", i) })?;
+            }
+            heroes.insert_with_ttl(5u32.to_be_bytes(), &PlayerV0 { name: "hero_5".to_string() }, Duration::from_secs(3600))?;
+        }
+
+        {
+            let conn = reopen(&db_path)?;
+            let orm = conn.get_orm();
+            let heroes = orm.tree("heroes")?;
+            heroes.create_index("by_name", |p: &PlayerV0| p.name.clone())?;
+
+            // Mientras se convierte la clave 0 otro escritor cambia la 1: la
+            // migración no pisa su valor. El closure suelta el Tree al usarlo
+            // para no mantener abierta la base.
+            let writer = std::sync::Mutex::new(Some(heroes.clone()));
+            conn.migrations("heroes").map(0, move |old: PlayerV0| {
+                if let Some(writer) = writer.lock().unwrap().take() {
+                    writer.insert(1u32.to_be_bytes(), &PlayerV1 { name: "INTRUDER".to_string(), xp: 99 }).unwrap();
+                }
+                PlayerV1 { name: old.name.to_uppercase(), xp: 0 }
+            });
+            orm.tree("heroes")?;
+
+            assert_eq!(heroes.get::<_, PlayerV1>(1u32.to_be_bytes())?.map(|p| p.xp), Some(99));
+            assert_eq!(heroes.get::<_, PlayerV1>(2u32.to_be_bytes())?.map(|p| p.name), Some("HERO_2".to_string()));
+            assert!(heroes.ttl(5u32.to_be_bytes())?.is_some());
+
+            // El índice registrado se reconstruyó con los valores migrados
+            assert_eq!(heroes.find_by_index::<_, PlayerV1>("by_name", &"HERO_3".to_string())?.len(), 1);
+            assert!(heroes.find_by_index::<_, PlayerV1>("by_name", &"hero_3".to_string())?.is_empty());
+            assert_eq!(heroes.find_by_index::<_, PlayerV1>("by_name", &"INTRUDER".to_string())?.len(), 1);
+        }
+
+        // El que no estaba registrado se reconstruye al registrarlo
+        let conn = reopen(&db_path)?;
+        let heroes = conn.get_orm().tree("heroes")?;
+        heroes.create_index("by_first", |p: &PlayerV1| p.name[..1].to_string())?;
+        assert_eq!(heroes.find_by_index::<_, PlayerV1>("by_first", &"H".to_string())?.len(), 19);
+        assert!(heroes.find_by_index::<_, PlayerV1>("by_first", &"h".to_string())?.is_empty());
+
+        println!("✅ Migrations keep indexes and concurrent writes");
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct ProfileV2 {
+        name: String,
+        #[serde(default)]
+        badges: Vec<String>,
+    }
+
+    // Test de codecs configurables por conexión y por árbol
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_value_codecs() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::CodecKind;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_value_codecs #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        // JSON por árbol: autodescriptivo, admite campos nuevos con #[serde(default)]
+        let profiles = orm.tree_with_codec("profiles", CodecKind::Json)?;
+        assert_eq!(profiles.codec(), CodecKind::Json);
+        profiles.insert("u1", &PlayerV0 { name: "ana".to_string() })?;
+        assert_eq!(profiles.tree.get("u1")?.unwrap().as_ref(), br#"{"name":"ana"}"#);
+        let upgraded: ProfileV2 = profiles.get("u1")?.unwrap();
+        assert_eq!(upgraded, ProfileV2 { name: "ana".to_string(), badges: vec![] });
+
+        // El codec queda fijado para el árbol en esta conexión
+        assert_eq!(orm.tree("profiles")?.codec(), CodecKind::Json);
+        assert_eq!(orm.tree("other")?.codec(), CodecKind::Bincode);
+
+        // Los errores de decodificación conservan el error del codec como source
+        profiles.tree.insert("broken", "not json")?;
+        let err = profiles.get::<_, ProfileV2>("broken").unwrap_err();
+        assert!(matches!(err, OrmError::Decode { .. }));
+        assert!(std::error::Error::source(&err).unwrap().downcast_ref::<serde_json::Error>().is_some());
+
+        // Codec por defecto de la conexión
+        let json_conn = conn.clone().with_codec(CodecKind::Json);
+        let tree = json_conn.get_orm().tree("json_default")?;
+        tree.insert("k", &42u32)?;
+        assert_eq!(tree.tree.get("k")?.unwrap().as_ref(), b"42");
+
+        #[cfg(feature = "msgpack")]
+        {
+            let packed = orm.tree_with_codec("packed", CodecKind::MessagePack)?;
+            packed.insert("u1", &upgraded)?;
+            assert_eq!(packed.get::<_, ProfileV2>("u1")?, Some(upgraded.clone()));
+        }
+
+        #[cfg(feature = "cbor")]
+        {
+            let cbor = orm.tree_with_codec("cbor", CodecKind::Cbor)?;
+            cbor.insert("u1", &upgraded)?;
+            assert_eq!(cbor.get::<_, ProfileV2>("u1")?, Some(upgraded.clone()));
+        }
+
+        // Formato propio como objeto: JSON con una cabecera de versión
+        struct Tagged;
+
+        impl sled_orm::ByteCodec for Tagged {
+            fn base(&self) -> CodecKind {
+                CodecKind::Json
+            }
+
+            fn encode(&self, bytes: Vec<u8>) -> Result<Vec<u8>, sled_orm::CodecError> {
+                Ok([b"v1:".as_slice(), &bytes].concat())
+            }
+
+            fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>, sled_orm::CodecError> {
+                Ok(bytes.strip_prefix(b"v1:").ok_or("unknown format")?.to_vec())
+            }
+        }
+
+        let tagged = CodecKind::custom(Tagged);
+        let custom = orm.tree_with_codec("custom", tagged.clone())?;
+        assert_eq!(custom.codec(), tagged);
+        custom.insert("u1", &upgraded)?;
+        assert_eq!(custom.tree.get("u1")?.unwrap().as_ref(), br#"v1:{"name":"ana","badges":[]}"#);
+        assert_eq!(custom.get::<_, ProfileV2>("u1")?, Some(upgraded.clone()));
+        custom.tree.insert("old", br#"{"name":"ana","badges":[]}"#.as_slice())?;
+        assert!(matches!(custom.get::<_, ProfileV2>("old"), Err(OrmError::Decode { .. })));
+
+        println!("✅ Value codecs work");
+        Ok(())
+    }
+
+    // Test de compresión transparente de valores
+    #[cfg(all(feature = "zstd", feature = "lz4"))]
+    #[test]
+    fn test_value_compression() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::Compression;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_value_compression #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        let embed = "lorem ipsum dolor sit amet ".repeat(200);
+        let logs = orm.tree_with_compression("message_logs", Compression::zstd(3).threshold(256))?;
+        logs.insert("big", &embed)?;
+        logs.insert("small", &"hi".to_string())?;
+
+        // Los valores grandes se comprimen, los pequeños sólo llevan la cabecera
+        let big = logs.tree.get("big")?.unwrap();
+        assert_eq!(big[0], 1);
+        assert!(big.len() < embed.len() / 10);
+        let small = logs.tree.get("small")?.unwrap();
+        assert_eq!(small[0], 0);
+
+        assert_eq!(logs.get::<_, String>("big")?, Some(embed.clone()));
+        assert_eq!(logs.get::<_, String>("small")?, Some("hi".to_string()));
+        assert_eq!(logs.all::<String>()?.len(), 2);
+        assert_eq!(logs.find(|v: &String| v.len() > 100)?, vec![embed.clone()]);
+
+        // Cambiar a lz4 deja legibles los valores escritos con zstd
+        let logs = orm.tree_with_compression("message_logs", Compression::lz4())?;
+        logs.insert("lz4", &embed)?;
+        assert_eq!(logs.tree.get("lz4")?.unwrap()[0], 2);
+        assert_eq!(logs.get::<_, String>("big")?, Some(embed.clone()));
+        assert_eq!(logs.get::<_, String>("lz4")?, Some(embed.clone()));
+
+        // Una cabecera desconocida es un error de decodificación
+        logs.tree.insert("corrupt", vec![9u8, 1, 2, 3])?;
+        assert!(matches!(logs.get::<_, String>("corrupt"), Err(OrmError::Decode { .. })));
+
+        // Los árboles sin compresión no cambian de formato
+        let plain = orm.tree("plain")?;
+        plain.insert("big", &embed)?;
+        assert!(plain.tree.get("big")?.unwrap().len() > embed.len());
+
+        println!("✅ Value compression works");
+        Ok(())
+    }
+
+    // Test de pasos de migración sobre un árbol comprimido
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_migration_steps_on_compressed_trees() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::Compression;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_migration_steps_on_compressed_trees #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let long_name = "player ".repeat(100);
+        {
+            let conn = Connection::new(db_path.to_str().unwrap())?;
+            let players = conn.get_orm().tree_with_compression("players", Compression::zstd(3).threshold(64))?;
+            players.insert(1u32.to_be_bytes(), &PlayerV0 { name: long_name.clone() })?;
+            players.insert(2u32.to_be_bytes(), &PlayerV0 { name: "short".to_string() })?;
+            assert_eq!(players.tree.get(1u32.to_be_bytes())?.unwrap()[0], 1);
+        }
+
+        // El paso recibe los bytes del codec, no los comprimidos
+        let conn = reopen(&db_path)?;
+        conn.migrations("players").step(0, |old: &[u8]| {
+            let (old, _): (PlayerV0, _) = bincode::serde::decode_from_slice(old, sled_orm::bincode_get_config())
+                .map_err(|e| OrmError::Aborted(e.to_string()))?;
+            Ok(PlayerV1 { name: old.name, xp: 7 })
+        });
+        let players = conn.get_orm().tree_with_compression("players", Compression::zstd(3).threshold(64))?;
+        assert_eq!(players.get::<_, PlayerV1>(1u32.to_be_bytes())?, Some(PlayerV1 { name: long_name, xp: 7 }));
+        assert_eq!(players.get::<_, PlayerV1>(2u32.to_be_bytes())?, Some(PlayerV1 { name: "short".to_string(), xp: 7 }));
+        assert_eq!(players.tree.get(1u32.to_be_bytes())?.unwrap()[0], 1);
+
+        println!("✅ Migration steps on compressed trees work");
+        Ok(())
+    }
+
+    #[cfg(all(feature = "aes-gcm", feature = "chacha20"))]
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct OAuthToken {
+        access_token: String,
+        refresh_token: String,
+    }
+
+    // Test de cifrado de valores en reposo
+    #[cfg(all(feature = "aes-gcm", feature = "chacha20"))]
+    #[test]
+    fn test_encryption_at_rest() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::Encryption;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_encryption_at_rest #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        let token = OAuthToken { access_token: "secret-access".to_string(), refresh_token: "secret-refresh".to_string() };
+        let tokens = orm.tree_with_encryption("tokens", Encryption::aes_gcm(&[7u8; 32]))?;
+        assert!(tokens.is_encrypted());
+        tokens.insert("user_1", &token)?;
+        tokens.insert("user_2", &token)?;
+        assert_eq!(tokens.get::<_, OAuthToken>("user_1")?, Some(token.clone()));
+
+        // Nada legible en disco y un nonce distinto por registro
+        let raw_1 = tokens.tree.get("user_1")?.unwrap();
+        let raw_2 = tokens.tree.get("user_2")?.unwrap();
+        assert!(!raw_1.windows(6).any(|w| w == b"secret"));
+        assert_ne!(raw_1, raw_2);
+
+        // Modificar un byte se detecta
+        let mut tampered = raw_1.to_vec();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        tokens.tree.insert("user_1", tampered)?;
+        let err = tokens.get::<_, OAuthToken>("user_1").unwrap_err();
+        assert!(matches!(err, OrmError::Decrypt { ref key, .. } if key == b"user_1"));
+
+        // Mover un valor cifrado a otra clave también
+        tokens.tree.insert("user_3", raw_2.clone())?;
+        assert!(matches!(tokens.get::<_, OAuthToken>("user_3"), Err(OrmError::Decrypt { .. })));
+
+        // Con otra clave no se puede leer
+        let wrong = orm.tree_with_encryption("tokens", Encryption::aes_gcm(&[8u8; 32]))?;
+        assert!(matches!(wrong.get::<_, OAuthToken>("user_2"), Err(OrmError::Decrypt { .. })));
+
+        // ChaCha20-Poly1305, combinado con índices
+        let accounts = orm.tree_with_encryption("accounts", Encryption::chacha20(&[9u8; 32]))?;
+        let account = Account { id: "a1".to_string(), email: "a@x.com".to_string(), guild_id: 3 };
+        account.save(&orm)?;
+        assert!(accounts.is_encrypted());
+        assert_eq!(Account::load(&orm, &"a1".to_string())?, Some(account.clone()));
+        assert_eq!(accounts.find_by_index::<_, Account>("guild_id", &3u64)?.len(), 1);
+
+        println!("✅ Encryption at rest works");
+        Ok(())
+    }
+
+    // Test de rotación de claves de cifrado
+    #[cfg(all(feature = "aes-gcm", feature = "chacha20"))]
+    #[test]
+    fn test_key_rotation() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::{Encryption, Keyring};
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_key_rotation #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let old_key = [1u8; 32];
+        let new_key = [2u8; 32];
+
+        {
+            let conn = Connection::new(db_path.to_str().unwrap())?;
+            conn.keyring().set_current_key(1, Encryption::aes_gcm(&old_key));
+            let orm = conn.get_orm();
+            let tokens = orm.tree_with_keyring("tokens")?;
+
+            for i in 0..300u32 {
+                let token = OAuthToken { access_token: format!("access_{}", i), refresh_token: format!("refresh_{}", i) };
+                tokens.insert(i.to_be_bytes(), &token)?;
+            }
+            assert_eq!(Keyring::key_id_of(&tokens.tree.get(0u32.to_be_bytes())?.unwrap()), Some(1));
+
+            // Nueva clave actual: lo viejo se sigue leyendo, lo nuevo usa la clave 2
+            conn.keyring().set_current_key(2, Encryption::chacha20(&new_key));
+            assert_eq!(conn.keyring().current_key_id(), Some(2));
+            tokens.insert(1000u32.to_be_bytes(), &OAuthToken { access_token: "a".to_string(), refresh_token: "r".to_string() })?;
+            assert_eq!(Keyring::key_id_of(&tokens.tree.get(1000u32.to_be_bytes())?.unwrap()), Some(2));
+            assert_eq!(tokens.get::<_, OAuthToken>(5u32.to_be_bytes())?.unwrap().access_token, "access_5");
+
+            // Las lecturas siguen funcionando mientras se rota en segundo plano
+            let rotation = tokens.rotate_keys_in_background();
+            while !rotation.is_finished() {
+                for i in (0..300u32).step_by(37) {
+                    let token: OAuthToken = tokens.get(i.to_be_bytes())?.unwrap();
+                    assert_eq!(token.access_token, format!("access_{}", i));
+                }
+            }
+            assert_eq!(rotation.join().unwrap()?, 300);
+            assert!(tokens.tree.iter().all(|item| Keyring::key_id_of(&item.unwrap().1) == Some(2)));
+
+            // Volver a rotar no tiene nada que hacer
+            assert_eq!(tokens.rotate_keys()?, 0);
+            conn.db.flush()?;
+        }
+
+        // Tras la rotación la clave vieja ya no hace falta
+        let conn = reopen(&db_path)?;
+        conn.keyring().set_current_key(2, Encryption::chacha20(&new_key));
+        let tokens = conn.get_orm().tree_with_keyring("tokens")?;
+        assert_eq!(tokens.all::<OAuthToken>()?.len(), 301);
+
+        println!("✅ Key rotation works");
+        Ok(())
+    }
+
+    // Test de registros con caducidad
+    #[test]
+    fn test_ttl_records() -> Result<(), Box<dyn std::error::Error>> {
+        use std::time::Duration;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_ttl_records #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let cooldowns = orm.tree("cooldowns")?;
+
+        cooldowns.insert_with_ttl("daily:1", &1u32, Duration::from_millis(50))?;
+        cooldowns.insert_with_ttl("daily:2", &2u32, Duration::from_secs(3600))?;
+        cooldowns.insert("permanent", &3u32)?;
+        assert_eq!(cooldowns.get::<_, u32>("daily:1")?, Some(1));
+        assert!(cooldowns.ttl("daily:2")?.unwrap() > Duration::from_secs(3500));
+        assert!(cooldowns.ttl("permanent")?.is_none());
+
+        // Un insert normal quita la caducidad
+        cooldowns.insert_with_ttl("mute:1", &4u32, Duration::from_millis(50))?;
+        cooldowns.insert("mute:1", &5u32)?;
+        assert!(cooldowns.ttl("mute:1")?.is_none());
+
+        std::thread::sleep(Duration::from_millis(80));
+
+        // Caducado pero todavía sin purgar: no aparece en ninguna lectura
+        assert_eq!(cooldowns.get::<_, u32>("daily:1")?, None);
+        assert_eq!(cooldowns.find(|_: &u32| true)?.len(), 3);
+        assert_eq!(cooldowns.all::<u32>()?.len(), 3);
+        assert_eq!(cooldowns.prefix::<u32, _>("daily:").count(), 1);
+        assert_eq!(cooldowns.page::<u32>(None, 10)?.items.len(), 3);
+        assert!(!orm.collection::<String, u32>("cooldowns")?.contains(&"daily:1".to_string())?);
+        assert_eq!(cooldowns.transaction(|tx| tx.get::<_, u32>("daily:1"))?, None);
+        assert_eq!(cooldowns.tree.len(), 4);
+
+        assert_eq!(cooldowns.purge_expired()?, 1);
+        assert_eq!(cooldowns.tree.len(), 3);
+        assert_eq!(cooldowns.purge_expired()?, 0);
+
+        // Otra instancia del árbol ve las mismas caducidades
+        let reopened = orm.tree("cooldowns")?;
+        reopened.insert_with_ttl("cache:1", &6u32, Duration::from_millis(20))?;
+        assert!(cooldowns.ttl("cache:1")?.is_some());
+
+        // Barrido en segundo plano, por lotes
+        for i in 0..600u32 {
+            cooldowns.insert_with_ttl(format!("cache:{}", i + 2), &i, Duration::from_millis(20))?;
+        }
+        let sweeper = cooldowns.spawn_sweeper(Duration::from_millis(10));
+        let start = Instant::now();
+        while cooldowns.tree.len() > 3 && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(sweeper.stop()?, 601);
+        assert_eq!(cooldowns.tree.len(), 3);
+        assert_eq!(cooldowns.get::<_, u32>("daily:2")?, Some(2));
+
+        println!("✅ TTL records work");
+        Ok(())
+    }
+
+    // Test de valores únicos de registros caducados sin purgar
+    #[test]
+    fn test_expired_unique_reservations() -> Result<(), Box<dyn std::error::Error>> {
+        use std::time::Duration;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_expired_unique_reservations #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let invites = conn.get_orm().tree("invites")?;
+        invites.create_unique_index("email", |u: &TestUser| u.email.clone())?;
+
+        let alice = TestUser::new("a", "Alice", "alice@example.com", 30);
+        invites.insert_with_ttl("a", &alice, Duration::from_millis(30))?;
+        invites.insert_with_ttl("c", &TestUser::new("c", "Carol", "carol@example.com", 40), Duration::from_secs(3600))?;
+        std::thread::sleep(Duration::from_millis(60));
+
+        // El registro caducado no existe para la API y su valor único queda libre
+        assert_eq!(invites.get::<_, TestUser>("a")?, None);
+        let again = TestUser::new("b", "Alice", "alice@example.com", 31);
+        invites.insert("b", &again)?;
+        assert_eq!(invites.find_by_index::<_, TestUser>("email", &"alice@example.com".to_string())?, vec![again.clone()]);
+
+        // Los vivos siguen reservando el suyo
+        assert!(matches!(
+            invites.insert("d", &TestUser::new("d", "Carol", "carol@example.com", 41)),
+            Err(OrmError::UniqueViolation { .. })
+        ));
+
+        // Purgar el caducado no libera la reserva del nuevo dueño
+        assert_eq!(invites.purge_expired()?, 1);
+        assert_eq!(invites.find_by_index::<_, TestUser>("email", &"alice@example.com".to_string())?, vec![again]);
+        assert!(invites.insert("e", &TestUser::new("e", "Eve", "alice@example.com", 20)).is_err());
+
+        println!("✅ Expired unique reservations are free");
+        Ok(())
+    }
+
+    // Test de suscripciones a cambios tipados
+    #[test]
+    fn test_change_subscriptions() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::Change;
+        use std::time::Duration;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_change_subscriptions #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let settings = orm.tree("settings")?;
+
+        let mut guild_1 = settings.subscribe::<GuildSettings>("guild_1:");
+        let mut everything = settings.subscribe::<GuildSettings>("");
+
+        let value = GuildSettings { prefix: "!".to_string(), volume: 50 };
+        let writer = {
+            let settings = orm.tree("settings")?;
+            let value = value.clone();
+            std::thread::spawn(move || -> Result<(), OrmError> {
+                settings.insert("guild_1:music", &value)?;
+                settings.insert("guild_2:music", &value)?;
+                settings.delete("guild_1:music")?;
+                Ok(())
+            })
+        };
+        writer.join().unwrap()?;
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(
+            guild_1.next().unwrap()?,
+            Change::Inserted { key: "guild_1:music".into(), value: value.clone() }
+        );
+        assert_eq!(guild_1.next_timeout(timeout).unwrap()?, Change::Removed { key: "guild_1:music".into() });
+        assert!(guild_1.next_timeout(Duration::from_millis(50)).is_none());
+
+        let keys: Vec<_> = (0..3).map(|_| everything.next_timeout(timeout).unwrap().unwrap().key().clone()).collect();
+        assert_eq!(keys, vec!["guild_1:music", "guild_2:music", "guild_1:music"]);
+
+        // Claves tipadas desde una Collection, y escrituras que pasan por índices
+        let accounts = orm.collection::<String, Account>("accounts")?;
+        Account::tree(&orm)?;
+        let mut changes = accounts.subscribe("");
+        let account = Account { id: "a1".to_string(), email: "a@x.com".to_string(), guild_id: 1 };
+        account.save(&orm)?;
+        match changes.next_timeout(timeout).unwrap()? {
+            Change::Inserted { key, value } => assert_eq!((key, value), ("a1".to_string(), account)),
+            other => panic!("unexpected change {:?}", other),
+        }
+
+        // Un valor que no se puede decodificar llega como error
+        settings.insert("guild_1:broken", &true)?;
+        assert!(guild_1.next_timeout(timeout).unwrap().is_err());
+
+        println!("✅ Change subscriptions work");
+        Ok(())
+    }
+
+    // Test de suscripciones como Stream asíncrono
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_subscriptions() -> Result<(), Box<dyn std::error::Error>> {
+        use futures::StreamExt;
+        use sled_orm::Change;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_async_subscriptions #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let xp = orm.collection::<u64, u32>("xp")?;
+
+        let mut stream = xp.subscribe("").into_stream();
+        xp.insert(&7, &100)?;
+        xp.delete(&7)?;
+
+        let changes = futures::executor::block_on(async {
+            let mut changes = Vec::new();
+            while changes.len() < 2 {
+                changes.push(stream.next().await.unwrap());
+            }
+            changes
+        });
+        assert_eq!(changes[0].as_ref().unwrap(), &Change::Inserted { key: 7, value: 100 });
+        assert_eq!(changes[1].as_ref().unwrap(), &Change::Removed { key: 7 });
+
+        println!("✅ Async subscriptions work");
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "notes", hooks)]
+    struct Note {
+        #[primary_key]
+        id: u64,
+        text: String,
+        revision: u32,
+    }
+
+    static NOTES_DELETED: AtomicUsize = AtomicUsize::new(0);
+
+    impl sled_orm::ModelHooks for Note {
+        fn before_save(&mut self) -> Result<(), OrmError> {
+            if self.text.is_empty() {
+                return Err(OrmError::Aborted("empty note".to_string()));
+            }
+            self.revision += 1;
+            Ok(())
+        }
+
+        fn after_delete(&self) {
+            NOTES_DELETED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    // Test de hooks de ciclo de vida por árbol, en lotes y en modelos
+    #[test]
+    fn test_lifecycle_hooks() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::Mutex;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_lifecycle_hooks #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+        let users = orm.tree("users")?;
+
+        // before_save normaliza el email y veta los menores de edad
+        users.before_save("normalize", |_, user: &mut TestUser| {
+            user.email = user.email.to_lowercase();
+            Ok(())
+        });
+        users.before_save("adults_only", |_, user: &mut TestUser| match user.age {
+            18.. => Ok(()),
+            _ => Err(OrmError::Aborted(format!("{} is underage", user.name))),
+        });
+        // Registrar otra vez el mismo nombre no duplica el hook
+        users.before_save("normalize", |_, user: &mut TestUser| {
+            user.name.push('!');
+            Ok(())
+        });
+
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        let deleted = Arc::new(Mutex::new(Vec::new()));
+        let log = saved.clone();
+        users.after_save("log", move |_, user: &TestUser| log.lock().unwrap().push(user.clone()));
+        let log = deleted.clone();
+        users.after_delete("log", move |key, user: &TestUser| {
+            log.lock().unwrap().push((key.to_vec(), user.name.clone()))
+        });
+        users.before_delete("protect_admin", |key, _: &TestUser| match key {
+            b"admin" => Err(OrmError::Aborted("admin can't be deleted".to_string())),
+            _ => Ok(()),
+        });
+
+        users.insert("alice", &TestUser::new("alice", "Alice", "Alice@Example.COM", 30))?;
+        let alice = users.get::<_, TestUser>("alice")?.unwrap();
+        assert_eq!(alice.email, "alice@example.com");
+        assert_eq!(alice.name, "Alice");
+        assert_eq!(saved.lock().unwrap().as_slice(), std::slice::from_ref(&alice));
+
+        // El veto no escribe nada ni llama a after_save
+        let vetoed = users.insert("bob", &TestUser::new("bob", "Bob", "bob@example.com", 12));
+        assert!(matches!(vetoed, Err(OrmError::Aborted(_))));
+        assert!(users.get::<_, TestUser>("bob")?.is_none());
+        assert_eq!(saved.lock().unwrap().len(), 1);
+
+        users.update("alice", &TestUser::new("alice", "Alice", "ALICE@new.com", 31))?;
+        assert_eq!(saved.lock().unwrap()[1].email, "alice@new.com");
+
+        users.insert("admin", &TestUser::new("admin", "Admin", "admin@example.com", 40))?;
+        assert!(matches!(users.delete("admin"), Err(OrmError::Aborted(_))));
+        assert!(users.get::<_, TestUser>("admin")?.is_some());
+
+        // after_delete recibe el valor borrado; borrar algo inexistente no lo llama
+        users.delete("alice")?;
+        users.delete("nobody")?;
+        assert_eq!(deleted.lock().unwrap().as_slice(), &[(b"alice".to_vec(), "Alice".to_string())]);
+
+        // En un lote un veto cancela todo y se informa con su posición
+        let mut batch = users.batch();
+        batch
+            .insert("carol", &TestUser::new("carol", "Carol", "CAROL@example.com", 25))
+            .insert("dave", &TestUser::new("dave", "Dave", "dave@example.com", 9))
+            .remove("admin");
+        match batch.apply() {
+            Err(OrmError::Batch { errors, .. }) => {
+                let indexes: Vec<_> = errors.iter().map(|e| e.index).collect();
+                assert_eq!(indexes, vec![1, 2]);
+            }
+            other => panic!("expected batch error, got {:?}", other),
+        }
+        assert!(users.get::<_, TestUser>("carol")?.is_none());
+
+        let mut batch = users.batch();
+        batch.insert("carol", &TestUser::new("carol", "Carol", "CAROL@example.com", 25)).remove("nobody");
+        batch.apply()?;
+        assert_eq!(users.get::<_, TestUser>("carol")?.unwrap().email, "carol@example.com");
+        assert_eq!(saved.lock().unwrap().last().unwrap().name, "Carol");
+
+        // Hooks de modelo activados con #[model(hooks)]
+        let note = Note { id: 1, text: "hello".to_string(), revision: 0 };
+        note.save(&orm)?;
+        note.save(&orm)?;
+        assert_eq!(Note::load(&orm, &1)?.unwrap().revision, 1);
+        let empty = Note { id: 2, text: String::new(), revision: 0 };
+        assert!(matches!(empty.save(&orm), Err(OrmError::Aborted(_))));
+        assert!(Note::load(&orm, &2)?.is_none());
+        note.delete(&orm)?;
+        assert_eq!(NOTES_DELETED.load(Ordering::SeqCst), 1);
+
+        println!("✅ Lifecycle hooks work");
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "guilds", has_many(members = GuildMember))]
+    struct Guild {
+        #[primary_key]
+        id: u64,
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "guild_members", has_many(invitees = GuildMember))]
+    struct GuildMember {
+        #[primary_key]
+        id: u64,
+        name: String,
+        #[belongs_to(Guild)]
+        guild_id: u64,
+        #[belongs_to(GuildMember, name = "inviter")]
+        invited_by: Option<u64>,
+    }
+
+    impl GuildMember {
+        fn new(id: u64, name: &str, guild_id: u64, invited_by: Option<u64>) -> Self {
+            Self { id, name: name.to_string(), guild_id, invited_by }
+        }
+    }
+
+    // Test de relaciones belongs_to / has_many
+    #[test]
+    fn test_model_relations() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::BelongsTo;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_model_relations #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        let lyra = Guild { id: 1, name: "Lyra".to_string() };
+        let other = Guild { id: 2, name: "Other".to_string() };
+        lyra.save(&orm)?;
+        other.save(&orm)?;
+
+        let alice = GuildMember::new(10, "Alice", 1, None);
+        let bob = GuildMember::new(11, "Bob", 1, Some(10));
+        let carol = GuildMember::new(12, "Carol", 2, Some(10));
+        for member in [&alice, &bob, &carol] {
+            member.save(&orm)?;
+        }
+
+        assert_eq!(bob.guild(&orm)?, Some(lyra.clone()));
+        assert_eq!(bob.inviter(&orm)?, Some(alice.clone()));
+        assert_eq!(alice.inviter(&orm)?, None);
+
+        let mut members = lyra.members(&orm)?;
+        members.sort_by_key(|m| m.id);
+        assert_eq!(members, vec![alice.clone(), bob.clone()]);
+        assert_eq!(other.members(&orm)?, vec![carol.clone()]);
+        assert_eq!(alice.invitees(&orm)?.len(), 2);
+        assert_eq!(<GuildMember as BelongsTo<Guild>>::FOREIGN_KEY, "guild_id");
+
+        // El índice inverso se actualiza al mover o borrar hijos
+        let mut moved = bob.clone();
+        moved.guild_id = 2;
+        moved.invited_by = None;
+        moved.save(&orm)?;
+        assert_eq!(lyra.members(&orm)?, vec![alice.clone()]);
+        assert_eq!(other.members(&orm)?.len(), 2);
+        assert_eq!(alice.invitees(&orm)?, vec![carol.clone()]);
+        carol.delete(&orm)?;
+        assert_eq!(other.members(&orm)?, vec![moved]);
+
+        println!("✅ Model relations work");
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "servers", has_many(channels = Channel, warnings = Warning))]
+    struct Server {
+        #[primary_key]
+        id: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "channels", has_many(messages = Message, invites = Invite))]
+    struct Channel {
+        #[primary_key]
+        id: u64,
+        #[belongs_to(Server, on_delete = "cascade")]
+        server_id: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "messages")]
+    struct Message {
+        #[primary_key]
+        id: u64,
+        #[belongs_to(Channel, on_delete = "cascade")]
+        channel_id: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "invites")]
+    struct Invite {
+        #[primary_key]
+        code: String,
+        #[belongs_to(Channel, on_delete = "set_null")]
+        channel_id: Option<u64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "warnings")]
+    struct Warning {
+        #[primary_key]
+        id: u64,
+        #[belongs_to(Server)]
+        server_id: u64,
+    }
+
+    // Test de integridad referencial: restrict, cascade y set_null
+    #[test]
+    fn test_referential_integrity() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::{BlockingReference, Key};
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_referential_integrity #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        // Sólo se abre el árbol del padre: has_many registra las restricciones
+        let server = Server { id: 1 };
+        server.save(&orm)?;
+        Server { id: 2 }.save(&orm)?;
+        let servers = orm.tree("servers")?;
+        Channel { id: 10, server_id: 1 }.save(&orm)?;
+        Channel { id: 11, server_id: 1 }.save(&orm)?;
+        Channel { id: 20, server_id: 2 }.save(&orm)?;
+        Message { id: 100, channel_id: 10 }.save(&orm)?;
+        Message { id: 101, channel_id: 11 }.save(&orm)?;
+        Message { id: 200, channel_id: 20 }.save(&orm)?;
+        Invite { code: "abc".to_string(), channel_id: Some(10) }.save(&orm)?;
+        let warning = Warning { id: 1000, server_id: 1 };
+        warning.save(&orm)?;
+
+        // Restrict: falla sin tocar nada y lista quién lo impide
+        match servers.delete(1u64.to_key_bytes()) {
+            Err(OrmError::ForeignKeyViolation { tree, references, .. }) => {
+                assert_eq!(tree, "servers");
+                assert_eq!(references, vec![BlockingReference {
+                    tree: "warnings".to_string(),
+                    foreign_key: "server_id".to_string(),
+                    key: 1000u64.to_key_bytes(),
+                }]);
+            }
+            other => panic!("expected foreign key violation, got {:?}", other),
+        }
+        assert_eq!(server.channels(&orm)?.len(), 2);
+        assert!(Message::load(&orm, &100)?.is_some());
+
+        // Cascade en cadena y set_null, todo en la misma transacción
+        warning.delete(&orm)?;
+        server.delete(&orm)?;
+        assert!(Server::load(&orm, &1)?.is_none());
+        assert!(Channel::load(&orm, &10)?.is_none() && Channel::load(&orm, &11)?.is_none());
+        assert!(Message::load(&orm, &100)?.is_none() && Message::load(&orm, &101)?.is_none());
+        assert_eq!(Invite::load(&orm, &"abc".to_string())?.unwrap().channel_id, None);
+        assert!(Channel { id: 10, server_id: 1 }.messages(&orm)?.is_empty());
+        assert!(Channel { id: 10, server_id: 1 }.invites(&orm)?.is_empty());
+
+        // Los registros de otros padres no se tocan
+        assert!(Channel::load(&orm, &20)?.is_some());
+        assert!(Message::load(&orm, &200)?.is_some());
+
+        println!("✅ Referential integrity works");
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "tickets", key = "snowflake", worker = 3)]
+    struct Ticket {
+        #[primary_key]
+        id: u64,
+        title: String,
+    }
+
+    // Test de claves generadas con insert_auto
+    #[test]
+    fn test_generated_keys() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::KeyStrategy;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_generated_keys #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        // Sequence por defecto: contador monotónico de sled
+        let events = orm.tree("events")?;
+        assert_eq!(events.key_strategy(), KeyStrategy::Sequence);
+        let first: u64 = events.insert_auto(&"joined".to_string())?;
+        let second: u64 = events.insert_auto(&"left".to_string())?;
+        assert!(second > first);
+        assert_eq!(orm.collection::<u64, String>("events")?.get(&second)?, Some("left".to_string()));
+        assert!(matches!(events.generate_key::<u32>(), Err(OrmError::InvalidKey { .. })));
+
+        // Snowflakes: únicos y crecientes aunque se agote la secuencia de un ms
+        let snow = orm.tree_with_key_strategy("snow", KeyStrategy::Snowflake { worker: 5 })?;
+        let keys = (0..5000).map(|_| snow.generate_key::<u64>()).collect::<Result<Vec<_>, _>>()?;
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(keys.iter().all(|key| (key >> 12) & 0x3ff == 5));
+        assert!(keys[0] >> 22 > 0);
+
+        // Un worker que no cabe en 10 bits repetiría los ids de otro
+        assert!(matches!(orm.tree_with_key_strategy("snow_1025", KeyStrategy::Snowflake { worker: 1025 }), Err(OrmError::InvalidWorker(1025))));
+        assert!(matches!(snow.set_key_strategy(KeyStrategy::Snowflake { worker: 1024 }), Err(OrmError::InvalidWorker(1024))));
+        assert_eq!(snow.key_strategy(), KeyStrategy::Snowflake { worker: 5 });
+        snow.set_key_strategy(KeyStrategy::Snowflake { worker: 1023 })?;
+        assert_eq!((snow.generate_key::<u64>()? >> 12) & 0x3ff, 1023);
+
+        // Model::insert_auto escribe la clave en el campo #[primary_key]
+        let mut ticket = Ticket { id: 0, title: "broken bot".to_string() };
+        let key = ticket.insert_auto(&orm)?;
+        assert_eq!(ticket.id, key);
+        assert_eq!(Ticket::load(&orm, &key)?, Some(ticket.clone()));
+        assert_eq!(Ticket::tree(&orm)?.key_strategy(), KeyStrategy::Snowflake { worker: 3 });
+        assert_eq!((key >> 12) & 0x3ff, 3);
+
+        // Una clave generada que ya existe no sobrescribe el registro
+        let taken: u64 = events.generate_key()?;
+        events.insert((taken + 1).to_be_bytes(), &"someone else".to_string())?;
+        assert!(matches!(events.insert_auto::<u64, _>(&"mine".to_string()), Err(OrmError::Conflict { .. })));
+        assert_eq!(events.get::<_, String>((taken + 1).to_be_bytes())?, Some("someone else".to_string()));
+
+        #[cfg(feature = "uuid")]
+        {
+            let sessions = orm.tree_with_key_strategy("sessions", KeyStrategy::UuidV7)?;
+            let a: uuid::Uuid = sessions.insert_auto(&1u32)?;
+            let b: uuid::Uuid = sessions.insert_auto(&2u32)?;
+            assert_eq!(a.get_version_num(), 7);
+            assert!(a < b);
+            assert_eq!(sessions.get::<_, u32>(b.as_bytes())?, Some(2));
+            let random = orm.tree_with_key_strategy("random", KeyStrategy::UuidV4)?;
+            assert_eq!(random.insert_auto::<uuid::Uuid, _>(&0u32)?.get_version_num(), 4);
+        }
+
+        #[cfg(feature = "ulid")]
+        {
+            let logs = orm.tree_with_key_strategy("logs", KeyStrategy::Ulid)?;
+            let keys = (0..100).map(|i| logs.insert_auto::<ulid::Ulid, _>(&i)).collect::<Result<Vec<_>, _>>()?;
+            assert!(keys.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(orm.collection::<ulid::Ulid, i32>("logs")?.all()?.len(), 100);
+        }
+
+        println!("✅ Generated keys work");
+        Ok(())
+    }
+
+    static SETTINGS_CONFIGURED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Settings {
+        guild: u64,
+        prefix: String,
+    }
+
+    // Modelo escrito a mano para contar las llamadas a configure; la primera falla
+    impl Model for Settings {
+        type PrimaryKey = u64;
+
+        const TREE: &'static str = "settings";
+
+        fn key(&self) -> &u64 {
+            &self.guild
+        }
+
+        fn configure(tree: &sled_orm::Tree) -> Result<(), OrmError> {
+            if SETTINGS_CONFIGURED.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(OrmError::Aborted("not ready".into()));
+            }
+            tree.create_index("prefix", |settings: &Settings| settings.prefix.clone())
+        }
+    }
+
+    // Test de que abrir árboles no repite el trabajo de la primera vez
+    #[test]
+    fn test_tree_open_is_cached() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_tree_open_is_cached #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        // Un configure fallido se reintenta; uno correcto no se repite
+        assert!(Settings::tree(&orm).is_err());
+        for guild in 0..3 {
+            Settings { guild, prefix: "!".to_string() }.save(&orm)?;
+        }
+        assert_eq!(SETTINGS_CONFIGURED.load(Ordering::SeqCst), 2);
+        assert_eq!(Settings::tree(&orm)?.find_keys_by_index("prefix", &"!".to_string())?.len(), 3);
+
+        // La caducidad se carga al reabrir la base
+        {
+            let cooldowns = orm.tree("reopen_cooldowns")?;
+            cooldowns.insert_with_ttl("a", &1u32, std::time::Duration::from_millis(1))?;
+            cooldowns.insert("b", &2u32)?;
+        }
+        drop(orm);
+        drop(conn);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let cooldowns = conn.get_orm().tree("reopen_cooldowns")?;
+        assert!(cooldowns.get::<_, u32>("a")?.is_none());
+        assert_eq!(conn.get_orm().tree("reopen_cooldowns")?.get::<_, u32>("b")?, Some(2));
+
+        println!("✅ Tree opening is cached");
+        Ok(())
+    }
+
+    // Test de construcción de índices sobre árboles con datos
+    #[test]
+    fn test_index_builds() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_index_builds #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        // Un índice único que no se puede construir no queda registrado
+        let users = Arc::new(orm.tree("users")?);
+        for i in 0..3 {
+            let user = TestUser::new(&format!("u{}", i), "Armin", "armin@example.com", 15 + i);
+            users.insert(&user.id, &user)?;
+        }
+        assert!(matches!(
+            users.create_unique_index("email", |u: &TestUser| u.email.clone()),
+            Err(OrmError::UniqueViolation { .. })
+        ));
+        assert!(matches!(
+            users.find_keys_by_index("email", &"armin@example.com".to_string()),
+            Err(OrmError::UnknownIndex { .. })
+        ));
+        assert!(conn.db.open_tree("__idx:users:email")?.is_empty());
+        users.insert("u3", &TestUser::new("u3", "Armin", "armin@example.com", 18))?;
+
+        // Los restos de una construcción interrumpida se descartan
+        conn.db.open_tree("__idx:users:age")?.insert(b"\0\0\0\x01?garbage", b"")?;
+        users.create_index("age", |u: &TestUser| u.age)?;
+        assert_eq!(users.find_keys_by_index("age", &15_u32)?, vec![sled::IVec::from(b"u0")]);
+        assert!(conn.db.open_tree("__idx:users:age")?.get(b"\0\0\0\x01?garbage")?.is_none());
+
+        // Las escrituras concurrentes con la construcción quedan indexadas
+        for i in 0..2000 {
+            users.insert(format!("n{:04}", i), &TestUser::new("n", "Jean", &format!("jean{}@example.com", i), 19))?;
+        }
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let users = users.clone();
+                std::thread::spawn(move || -> Result<(), OrmError> {
+                    for i in 0..100 {
+                        let key = format!("n{:04}", t * 100 + i);
+                        users.insert(&key, &TestUser::new("n", "Jean", "moved@example.com", 20))?;
+                        users.insert(format!("w{}_{}", t, i), &TestUser::new("w", "Sasha", "new@example.com", 20))?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        users.create_index("email_host", |u: &TestUser| u.email.clone())?;
+        for writer in writers {
+            writer.join().unwrap()?;
+        }
+        assert_eq!(users.find_keys_by_index("email_host", &"moved@example.com".to_string())?.len(), 400);
+        assert_eq!(users.find_keys_by_index("email_host", &"new@example.com".to_string())?.len(), 400);
+        assert!(users.find_keys_by_index("email_host", &"jean0@example.com".to_string())?.is_empty());
+        assert_eq!(users.find_keys_by_index("email_host", &"jean400@example.com".to_string())?.len(), 1);
+
+        // rebuild_index corrige entradas que se saltaron el índice
+        let bytes = bincode::serde::encode_to_vec(TestUser::new("u0", "Armin", "armin@example.com", 99), sled_orm::bincode_get_config())?;
+        users.tree.insert("u0", bytes)?;
+        users.rebuild_index("age")?;
+        assert!(users.find_keys_by_index("age", &15_u32)?.is_empty());
+        assert_eq!(users.find_keys_by_index("age", &99_u32)?, vec![sled::IVec::from(b"u0")]);
+
+        println!("✅ Index builds work");
+        Ok(())
+    }
+
+    // Test de hooks en todos los caminos de escritura
+    #[test]
+    fn test_hooks_on_every_write_path() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::Mutex;
+        use std::time::Duration;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_hooks_on_every_write_path #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        // Los saldos negativos se guardan como 0 y los de más de 1000 se vetan
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let wallets = orm.tree("wallets")?;
+        wallets.before_save("clamp", |_, balance: &mut i64| {
+            *balance = (*balance).max(0);
+            Ok(())
+        });
+        wallets.before_save("limit", |_, balance: &mut i64| match *balance {
+            ..=1000 => Ok(()),
+            _ => Err(OrmError::Aborted("too rich".to_string())),
+        });
+        wallets.before_delete("keep_bank", |key, _: &i64| match key {
+            b"bank" => Err(OrmError::Aborted("the bank stays".to_string())),
+            _ => Ok(()),
+        });
+        let saved = log.clone();
+        wallets.after_save("log", move |key, balance: &i64| {
+            saved.lock().unwrap().push(format!("save {} {}", String::from_utf8_lossy(key), balance))
+        });
+        let deleted = log.clone();
+        wallets.after_delete("log", move |key, _: &i64| {
+            deleted.lock().unwrap().push(format!("delete {}", String::from_utf8_lossy(key)))
+        });
+        let take = || std::mem::take(&mut *log.lock().unwrap());
+
+        // update_with y compare_and_swap
+        wallets.update_with("a", |balance: Option<i64>| Some(balance.unwrap_or(0) - 5))?;
+        assert_eq!(wallets.get::<_, i64>("a")?, Some(0));
+        assert!(matches!(wallets.compare_and_swap("a", Some(&0_i64), Some(&2000)), Err(OrmError::Aborted(_))));
+        wallets.compare_and_swap("a", Some(&0_i64), Some(&7))?;
+        assert_eq!(take(), vec!["save a 0", "save a 7"]);
+
+        // insert_with_ttl
+        wallets.insert_with_ttl("b", &-1_i64, Duration::from_secs(60))?;
+        assert_eq!(wallets.get::<_, i64>("b")?, Some(0));
+        assert_eq!(take(), vec!["save b 0"]);
+
+        // Transacciones: los after_* sólo llegan si se confirma
+        wallets.transaction(|tx| {
+            tx.insert("c", &-3_i64)?;
+            tx.remove("a")
+        })?;
+        assert_eq!(wallets.get::<_, i64>("c")?, Some(0));
+        assert_eq!(take(), vec!["save c 0", "delete a"]);
+        let aborted: Result<(), _> = wallets.transaction(|tx| {
+            tx.insert("d", &1_i64)?;
+            tx.abort("changed my mind")
+        });
+        assert!(aborted.is_err());
+        assert!(matches!(wallets.transaction(|tx| tx.insert("d", &5000_i64)), Err(OrmError::Aborted(_))));
+        assert!(matches!(wallets.transaction(|tx| tx.remove("bank")), Ok(())));
+        wallets.insert("bank", &1000_i64)?;
+        assert!(matches!(wallets.transaction(|tx| tx.remove("bank")), Err(OrmError::Aborted(_))));
+        assert_eq!(take(), vec!["save bank 1000"]);
+
+        let ledger = orm.tree("hook_ledger")?;
+        orm.transaction((&wallets, &ledger), |(w, l)| {
+            w.insert("c", &-10_i64)?;
+            l.insert("entry", &"c reset".to_string())
+        })?;
+        assert_eq!(take(), vec!["save c 0"]);
+
+        // purge_expired: el veto deja el registro caducado y sigue con los demás
+        wallets.insert_with_ttl("bank", &1000_i64, Duration::from_millis(1))?;
+        wallets.insert_with_ttl("e", &1_i64, Duration::from_millis(1))?;
+        take();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(wallets.purge_expired()?, 1);
+        assert_eq!(take(), vec!["delete e"]);
+        assert!(wallets.get::<_, i64>("bank")?.is_none());
+        assert!(wallets.tree.contains_key("bank")?);
+
+        // Borrados en cascada
+        let messages_deleted = Arc::new(AtomicUsize::new(0));
+        let counter = messages_deleted.clone();
+        Message::tree(&orm)?.after_delete("count", move |_, _: &Message| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let server = Server { id: 1 };
+        server.save(&orm)?;
+        Channel { id: 10, server_id: 1 }.save(&orm)?;
+        Message { id: 100, channel_id: 10 }.save(&orm)?;
+        Message { id: 101, channel_id: 10 }.save(&orm)?;
+        server.delete(&orm)?;
+        assert_eq!(messages_deleted.load(Ordering::SeqCst), 2);
+
+        // Las migraciones reescriben los registros sin pasar por los hooks
+        conn.migrations("wallets").map(0, |balance: i64| balance + 5000);
+        let wallets = orm.tree("wallets")?;
+        assert_eq!(wallets.get::<_, i64>("c")?, Some(5000));
+        assert!(take().is_empty());
+
+        println!("✅ Hooks run on every write path");
+        Ok(())
+    }
+
+    // Test de on_delete en todas las vías de borrado
+    #[test]
+    fn test_references_on_every_delete_path() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::Key;
+        use std::time::Duration;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_references_on_every_delete_path #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        let servers = Server::tree(&orm)?;
+        let channels = Channel::tree(&orm)?;
+        let setup = |id: u64| -> Result<(), OrmError> {
+            Server { id }.save(&orm)?;
+            Channel { id: id * 10, server_id: id }.save(&orm)?;
+            Message { id: id * 100, channel_id: id * 10 }.save(&orm)
+        };
+        let gone = |id: u64| -> Result<bool, OrmError> {
+            Ok(Server::load(&orm, &id)?.is_none()
+                && Channel::load(&orm, &(id * 10))?.is_none()
+                && Message::load(&orm, &(id * 100))?.is_none())
+        };
+
+        // Lotes: Restrict veta el elemento y Cascade arrastra a los hijos
+        setup(1)?;
+        let warning = Warning { id: 1, server_id: 1 };
+        warning.save(&orm)?;
+        match servers.delete_many([1u64.to_key_bytes()]) {
+            Err(OrmError::Batch { errors, .. }) => {
+                assert!(matches!(errors[0].error, OrmError::ForeignKeyViolation { .. }))
+            }
+            other => panic!("expected batch error, got {:?}", other),
+        }
+        assert!(Channel::load(&orm, &10)?.is_some());
+        warning.delete(&orm)?;
+        servers.delete_many([1u64.to_key_bytes()])?;
+        assert!(gone(1)?);
+
+        // TypedTx::remove en Tree::transaction y ORM::transaction
+        setup(2)?;
+        servers.transaction(|tx| tx.remove(2u64.to_key_bytes()))?;
+        assert!(gone(2)?);
+        setup(3)?;
+        orm.transaction((&servers, &channels), |(servers, _)| servers.remove(3u64.to_key_bytes()))?;
+        assert!(gone(3)?);
+
+        // Un hijo creado en la misma transacción también se borra
+        setup(4)?;
+        orm.transaction((&servers, &channels), |(servers, channels)| {
+            channels.insert(41u64.to_key_bytes(), &Channel { id: 41, server_id: 4 })?;
+            servers.remove(4u64.to_key_bytes())
+        })?;
+        assert!(gone(4)? && Channel::load(&orm, &41)?.is_none());
+
+        // compare_and_swap y update_with hacia None
+        setup(5)?;
+        servers.compare_and_swap(5u64.to_key_bytes(), Some(&Server { id: 5 }), None)?;
+        assert!(gone(5)?);
+        setup(6)?;
+        servers.update_with(6u64.to_key_bytes(), |_: Option<Server>| None)?;
+        assert!(gone(6)?);
+
+        // purge_expired: Restrict deja el registro caducado y Cascade se lleva los hijos
+        servers.insert_with_ttl(7u64.to_key_bytes(), &Server { id: 7 }, Duration::from_millis(100))?;
+        Warning { id: 7, server_id: 7 }.save(&orm)?;
+        servers.insert_with_ttl(8u64.to_key_bytes(), &Server { id: 8 }, Duration::from_millis(100))?;
+        Channel { id: 80, server_id: 8 }.save(&orm)?;
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(servers.purge_expired()?, 1);
+        assert!(servers.tree.contains_key(7u64.to_key_bytes())?);
+        assert!(!servers.tree.contains_key(8u64.to_key_bytes())?);
+        assert!(Channel::load(&orm, &80)?.is_none());
+
+        println!("✅ References are enforced on every delete path");
+        Ok(())
+    }
+
+    // Test de claves foráneas que apuntan a padres inexistentes
+    #[test]
+    fn test_foreign_keys_need_parent() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::Key;
+        use std::time::Duration;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_foreign_keys_need_parent #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        Server { id: 1 }.save(&orm)?;
+        match (Channel { id: 10, server_id: 2 }).save(&orm) {
+            Err(OrmError::MissingParent { tree, foreign_key, parent_tree, parent_key, .. }) => {
+                assert_eq!((tree.as_str(), foreign_key.as_str(), parent_tree.as_str()), ("channels", "server_id", "servers"));
+                assert_eq!(parent_key, 2u64.to_key_bytes());
+            }
+            other => panic!("expected missing parent, got {:?}", other),
+        }
+        assert!(Channel::load(&orm, &10)?.is_none());
+
+        // Al cambiar la clave foránea también se comprueba
+        let mut channel = Channel { id: 10, server_id: 1 };
+        channel.save(&orm)?;
+        channel.server_id = 2;
+        assert!(matches!(channel.save(&orm), Err(OrmError::MissingParent { .. })));
+        assert_eq!(Channel::load(&orm, &10)?.map(|c| c.server_id), Some(1));
+
+        // Una clave foránea nula no necesita padre
+        Invite { code: "open".to_string(), channel_id: None }.save(&orm)?;
+
+        // El padre puede crearse en la misma transacción
+        let (servers, channels) = (Server::tree(&orm)?, Channel::tree(&orm)?);
+        orm.transaction((&servers, &channels), |(servers, channels)| {
+            servers.insert(2u64.to_key_bytes(), &Server { id: 2 })?;
+            channels.insert(20u64.to_key_bytes(), &Channel { id: 20, server_id: 2 })
+        })?;
+        assert_eq!(Channel::load(&orm, &20)?.map(|c| c.server_id), Some(2));
+
+        // Un padre caducado no cuenta
+        servers.insert_with_ttl(3u64.to_key_bytes(), &Server { id: 3 }, Duration::from_millis(50))?;
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(matches!((Channel { id: 30, server_id: 3 }).save(&orm), Err(OrmError::MissingParent { .. })));
+
+        println!("✅ Foreign keys need an existing parent");
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "clans")]
+    struct Clan {
+        #[primary_key]
+        id: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "recruits")]
+    struct Recruit {
+        #[primary_key]
+        id: u64,
+        #[belongs_to(Clan)]
+        clan_id: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "banners")]
+    struct Banner {
+        #[primary_key]
+        id: u64,
+        #[belongs_to(Clan, on_delete = "cascade")]
+        clan_id: u64,
+    }
+
+    // Test de referencias guardadas: el padre las conoce sin has_many ni abrir al hijo
+    #[test]
+    fn test_persisted_references() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::Key;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_persisted_references #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        {
+            let conn = Connection::new(db_path.to_str().unwrap())?;
+            let orm = conn.get_orm();
+            Clan { id: 1 }.save(&orm)?;
+            Recruit { id: 10, clan_id: 1 }.save(&orm)?;
+            Banner { id: 20, clan_id: 1 }.save(&orm)?;
+        }
+
+        let conn = reopen(&db_path)?;
+        let orm = conn.get_orm();
+        let clans = Clan::tree(&orm)?;
+
+        // Cascade sobre un hijo sin configurar no puede mantener sus índices
+        match clans.delete(1u64.to_key_bytes()) {
+            Err(OrmError::UnloadedRelation { tree, foreign_key }) => assert_eq!((tree.as_str(), foreign_key.as_str()), ("banners", "clan_id")),
+            other => panic!("expected unloaded relation, got {:?}", other),
+        }
+
+        // Restrict sí se comprueba con el índice guardado
+        Banner::tree(&orm)?;
+        assert!(matches!(clans.delete(1u64.to_key_bytes()), Err(OrmError::ForeignKeyViolation { .. })));
+        assert!(Clan::load(&orm, &1)?.is_some());
+
+        Recruit { id: 10, clan_id: 1 }.delete(&orm)?;
+        clans.delete(1u64.to_key_bytes())?;
+        assert!(Banner::load(&orm, &20)?.is_none());
+
+        println!("✅ Persisted references work");
+        Ok(())
+    }
+
+    // Test de inserciones de hijos concurrentes con el borrado del padre
+    #[test]
+    fn test_references_under_concurrency() -> Result<(), Box<dyn std::error::Error>> {
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_references_under_concurrency #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        for id in 0..50 {
+            Clan { id }.save(&orm)?;
+        }
+        Banner::tree(&orm)?;
+
+        // Cada banner o llega antes que el borrado (y cae en cascada) o falla
+        // con MissingParent; nunca queda huérfano
+        let inserter = {
+            let orm = conn.get_orm();
+            std::thread::spawn(move || -> Result<(), OrmError> {
+                for id in 0..500u64 {
+                    match (Banner { id, clan_id: id % 50 }).save(&orm) {
+                        Ok(()) | Err(OrmError::MissingParent { .. }) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(())
+            })
+        };
+        for id in 0..50 {
+            Clan { id }.delete(&orm)?;
+        }
+        inserter.join().unwrap()?;
+
+        for banner in Banner::tree(&orm)?.all::<Banner>()? {
+            assert!(Clan::load(&orm, &banner.clan_id)?.is_some(), "orphan banner {:?}", banner);
+        }
+
+        println!("✅ No orphans under concurrency");
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Model)]
+    #[model(tree = "shard_configs")]
+    struct ShardConfig {
+        #[primary_key]
+        guild: u64,
+        prefix: String,
+        #[version]
+        version: u64,
+    }
+
+    // Test del bloqueo optimista con un campo #[version]
+    #[test]
+    fn test_optimistic_locking_mode() -> Result<(), Box<dyn std::error::Error>> {
+        use sled_orm::Key;
+
+        let test_id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        println!("🧪 Starting test_optimistic_locking_mode #{}", test_id);
+
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join(format!("test_db_{}", test_id));
+        let conn = Connection::new(db_path.to_str().unwrap())?;
+        let orm = conn.get_orm();
+
+        // Un registro nuevo se guarda con versión 0 y queda en la 1
+        ShardConfig { guild: 1, prefix: "!".to_string(), version: 0 }.save(&orm)?;
+        let mut shard_a = ShardConfig::load(&orm, &1)?.unwrap();
+        let mut shard_b = shard_a.clone();
+        assert_eq!(shard_a.version, 1);
+
+        // Dos shards editan a la vez: el segundo recibe el valor guardado
+        shard_a.prefix = "?".to_string();
+        shard_a.save(&orm)?;
+        shard_b.prefix = "$".to_string();
+        let err = shard_b.save(&orm).unwrap_err();
+        assert!(err.is_conflict());
+        assert_eq!(err.current_value::<ShardConfig>(), Some(&ShardConfig { guild: 1, prefix: "?".to_string(), version: 2 }));
+        shard_b.reload(&orm)?;
+        shard_b.prefix = "$".to_string();
+        shard_b.save(&orm)?;
+        assert_eq!(ShardConfig::load(&orm, &1)?.map(|c| (c.prefix, c.version)), Some(("$".to_string(), 3)));
+
+        // Tree::insert y Tree::update también la comprueban
+        let configs = ShardConfig::tree(&orm)?;
+        let key = 1u64.to_key_bytes();
+        assert!(configs.update(&key, &ShardConfig { guild: 1, prefix: "stale".to_string(), version: 2 }).is_err());
+        assert!(configs.insert(2u64.to_key_bytes(), &ShardConfig { guild: 2, prefix: "!".to_string(), version: 7 }).is_err());
+        configs.update(&key, &ShardConfig { guild: 1, prefix: "%".to_string(), version: 3 })?;
+        assert_eq!(configs.get::<_, ShardConfig>(&key)?.map(|c| c.version), Some(4));
+
+        // Y los lotes y update_with
+        let mut batch = configs.batch();
+        batch.insert(&key, &ShardConfig { guild: 1, prefix: "stale".to_string(), version: 3 });
+        match batch.apply() {
+            Err(OrmError::Batch { errors, .. }) => assert!(errors[0].error.is_conflict()),
+            other => panic!("expected batch error, got {:?}", other),
+        }
+        configs.update_with(&key, |config: Option<ShardConfig>| {
+            config.map(|config| ShardConfig { prefix: "&".to_string(), ..config })
+        })?;
+        assert_eq!(ShardConfig::load(&orm, &1)?.map(|c| (c.prefix, c.version)), Some(("&".to_string(), 5)));
+
+        println!("✅ Optimistic locking mode works");
+        Ok(())
+    }
 }
\ No newline at end of file